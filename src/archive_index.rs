@@ -0,0 +1,200 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::FileType;
+use crate::HashError;
+use crate::MetadataError;
+use crate::ReadError;
+use crate::ReadLinkError;
+use crate::Result;
+use crate::stat::Stat;
+use crate::tree::TreeEntry;
+
+/// The members of an archive (or the contents of a git tree or a [`crate::MemoryTree`]), indexed
+/// by path.
+///
+/// Archives don't always list the parent directories of their members, so those are added
+/// implicitly. Implicit directories have no mode, owner, or modification time.
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveIndex {
+    path: PathBuf,
+    entries: HashMap<PathBuf, ArchiveEntry>,
+    /// The names of the children of each directory.
+    children: HashMap<PathBuf, BTreeSet<OsString>>,
+}
+
+/// A member of an archive.
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveEntry {
+    pub(crate) stat: Stat,
+    /// The index of the member holding this file's contents.
+    pub(crate) index: Option<usize>,
+    /// The hash of the file's contents, if it was computed while indexing the archive.
+    #[cfg_attr(not(feature = "tar"), allow(dead_code))]
+    pub(crate) hash: Option<blake3::Hash>,
+    pub(crate) target: Option<PathBuf>,
+}
+
+impl ArchiveEntry {
+    pub(crate) fn new(stat: Stat) -> Self {
+        Self {
+            stat,
+            index: None,
+            hash: None,
+            target: None,
+        }
+    }
+}
+
+impl ArchiveIndex {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            entries: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    /// The path of the archive.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add an entry, and any parent directories which haven't been listed yet.
+    ///
+    /// Later members replace earlier ones with the same path, like when extracting an archive. A
+    /// member inside an earlier file replaces that file with a directory, and a file replacing an
+    /// earlier directory replaces its contents too.
+    pub(crate) fn insert(&mut self, relative: PathBuf, entry: ArchiveEntry) {
+        if !entry.stat.is_dir() {
+            self.remove_contents(&relative);
+        }
+
+        let mut child = relative.as_path();
+        while let Some(parent) = child.parent() {
+            let name = child.file_name().expect("Normal paths have file names");
+            self.children
+                .entry(parent.to_owned())
+                .or_default()
+                .insert(name.to_owned());
+            if parent.as_os_str().is_empty() {
+                break;
+            }
+            let listed = self.entries.get(parent).map(|entry| entry.stat.is_dir());
+            if listed != Some(true) {
+                self.entries.insert(
+                    parent.to_owned(),
+                    ArchiveEntry::new(Stat::new(FileType::Dir, 0)),
+                );
+            }
+            if listed.is_some() {
+                // The parent's own parents were added when it was.
+                break;
+            }
+            child = parent;
+        }
+        self.entries.insert(relative, entry);
+    }
+
+    /// Remove the contents of the directory at `relative`, if there is one.
+    fn remove_contents(&mut self, relative: &Path) {
+        for name in self.children.remove(relative).into_iter().flatten() {
+            let child = relative.join(name);
+            self.remove_contents(&child);
+            self.entries.remove(&child);
+        }
+    }
+
+    pub(crate) fn get(&self, relative: &Path) -> Option<&ArchiveEntry> {
+        self.entries.get(relative)
+    }
+
+    pub(crate) fn get_mut(&mut self, relative: &Path) -> Option<&mut ArchiveEntry> {
+        self.entries.get_mut(relative)
+    }
+
+    fn entry(&self, relative: &Path) -> std::io::Result<&ArchiveEntry> {
+        self.entries.get(relative).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Path not found in archive")
+        })
+    }
+
+    pub(crate) fn read_dir(&self, relative: &Path) -> Vec<TreeEntry> {
+        self.children
+            .get(relative)
+            .into_iter()
+            .flatten()
+            .map(|name| TreeEntry {
+                name: name.clone(),
+                stat: Ok(self.entries[&relative.join(name)].stat.clone()),
+            })
+            .collect()
+    }
+
+    pub(crate) fn stat(&self, relative: &Path) -> Result<Stat> {
+        if relative.as_os_str().is_empty() {
+            return Ok(Stat::new(FileType::Dir, 0));
+        }
+        Ok(self
+            .entry(relative)
+            .map(|entry| entry.stat.clone())
+            .map_err(|inner| MetadataError {
+                path: self.path.join(relative),
+                inner,
+            })?)
+    }
+
+    pub(crate) fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        let target = self.entry(relative).and_then(|entry| {
+            entry.target.clone().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a symbolic link")
+            })
+        });
+        Ok(target.map_err(|inner| ReadLinkError {
+            path: self.path.join(relative),
+            inner,
+        })?)
+    }
+
+    /// The index of the member holding the contents of the file at `relative`.
+    pub(crate) fn member(&self, relative: &Path) -> Result<usize> {
+        let index = self.entry(relative).and_then(|entry| {
+            entry
+                .index
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a file"))
+        });
+        Ok(index.map_err(|inner| ReadError {
+            path: self.path.join(relative),
+            inner,
+        })?)
+    }
+
+    /// The hash of the file at `relative`, if it was computed while indexing the archive.
+    #[cfg_attr(not(feature = "tar"), allow(dead_code))]
+    pub(crate) fn hash(&self, relative: &Path) -> Result<Option<blake3::Hash>> {
+        let entry = self.entry(relative).map_err(|inner| HashError {
+            path: self.path.join(relative),
+            inner,
+        })?;
+        Ok(entry.hash)
+    }
+}
+
+/// Normalize the path of an archive member to a relative path, like `tar` does when extracting.
+///
+/// Returns `None` if the path could escape the root of the archive.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
@@ -0,0 +1,30 @@
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// One of the paths being diffed, which is either borrowed or shared between entries.
+#[derive(Debug, Clone)]
+pub(crate) enum Base<'a> {
+    Borrowed(&'a Path),
+    Shared(Arc<Path>),
+}
+
+impl Base<'_> {
+    pub(crate) fn into_owned(self) -> Base<'static> {
+        match self {
+            Base::Borrowed(path) => Base::Shared(path.into()),
+            Base::Shared(path) => Base::Shared(path),
+        }
+    }
+}
+
+impl Deref for Base<'_> {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Base::Borrowed(path) => path,
+            Base::Shared(path) => path,
+        }
+    }
+}
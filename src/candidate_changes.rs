@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use crate::Changes;
+use crate::DiffOpts;
+use crate::PathInfo;
+use crate::Result;
+
+/// Determine which attributes of a path present in both trees have changed.
+pub(crate) fn candidate_changes(
+    relative: &Path,
+    removed: &PathInfo<'_>,
+    candidate: &PathInfo<'_>,
+    opts: &DiffOpts,
+) -> Result<Changes> {
+    let removed_stat = &removed.stat;
+    let candidate_stat = &candidate.stat;
+
+    let content = if removed_stat.is_dir() && candidate_stat.is_dir() {
+        // Computed later from the directory's contents.
+        false
+    } else if removed_stat.is_dir() || candidate_stat.is_dir() {
+        true
+    } else if removed_stat.is_symlink() && candidate_stat.is_symlink() {
+        removed.tree.read_link(relative)? != candidate.tree.read_link(relative)?
+    } else if removed_stat.is_symlink() || candidate_stat.is_symlink() {
+        true
+    } else {
+        !opts.comparator.is_same(relative, removed, candidate)?
+    };
+
+    Ok(Changes {
+        content,
+        ..metadata_changes(removed, candidate, opts)
+    })
+}
+
+/// Determine which metadata attributes of a path present in both trees have changed, without
+/// comparing contents.
+///
+/// Attributes which aren't known for both paths are never considered changed.
+pub(crate) fn metadata_changes(
+    removed: &PathInfo<'_>,
+    candidate: &PathInfo<'_>,
+    opts: &DiffOpts,
+) -> Changes {
+    fn differs<T: PartialEq>(compare: bool, old: Option<T>, new: Option<T>) -> bool {
+        match (old, new) {
+            (Some(old), Some(new)) => compare && old != new,
+            _ => false,
+        }
+    }
+
+    let removed_stat = &removed.stat;
+    let candidate_stat = &candidate.stat;
+
+    let metadata = &opts.compare_metadata;
+    Changes {
+        content: false,
+        mode: differs(metadata.mode, removed_stat.mode, candidate_stat.mode),
+        owner: differs(metadata.owner, removed_stat.owner, candidate_stat.owner),
+        mtime: differs(metadata.mtime, removed_stat.mtime, candidate_stat.mtime),
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::Display;
+
+/// The attributes of a path which differ between the old and new trees.
+///
+/// Only metadata attributes selected with [`crate::DiffOpts::compare_metadata`] are compared.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Changes {
+    pub(crate) content: bool,
+    pub(crate) mode: bool,
+    pub(crate) owner: bool,
+    pub(crate) mtime: bool,
+}
+
+impl Changes {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the contents changed.
+    ///
+    /// For directories, this indicates that some path in the directory changed. For symbolic
+    /// links (when [`crate::DiffOpts::follow_links`] is disabled), this indicates that the link
+    /// target changed.
+    pub fn content(&self) -> bool {
+        self.content
+    }
+
+    /// Whether the permission bits changed.
+    pub fn mode(&self) -> bool {
+        self.mode
+    }
+
+    /// Whether the owning user or group changed.
+    pub fn owner(&self) -> bool {
+        self.owner
+    }
+
+    /// Whether the modification time changed.
+    pub fn mtime(&self) -> bool {
+        self.mtime
+    }
+
+    /// Whether any metadata (anything other than the contents) changed.
+    pub(crate) fn metadata(&self) -> bool {
+        self.mode || self.owner || self.mtime
+    }
+}
+
+/// A comma-separated list of the changed attributes, like `content, mode`.
+impl Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (self.content, "content"),
+            (self.mode, "mode"),
+            (self.owner, "owner"),
+            (self.mtime, "mtime"),
+        ];
+        let mut first = true;
+        for (_, name) in names.iter().filter(|(changed, _)| *changed) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use crate::PathInfo;
+use crate::Result;
+use crate::stat::Checksum;
+
+/// Decides whether a file present in both trees has the same contents on each side.
+///
+/// A [`Comparator`] is only consulted for paths which exist in both trees and are not
+/// directories or (when [`crate::DiffOpts::follow_links`] is disabled) symbolic links. Paths
+/// which are equal are reported as [`crate::DiffTag::Equal`], and paths which are not equal are
+/// reported as [`crate::DiffTag::Replace`].
+///
+/// The default implementation is [`Blake3Comparator`].
+pub trait Comparator {
+    /// Compare the file at `relative` in the old and new trees.
+    ///
+    /// Use [`PathInfo::open`] or [`PathInfo::read`] to read the files' contents, which works for
+    /// any [`crate::Tree`]. Joining `relative` to [`PathInfo::base`] only gives a usable path
+    /// when diffing directories.
+    fn is_same(&self, relative: &Path, old: &PathInfo<'_>, new: &PathInfo<'_>) -> Result<bool>;
+}
+
+/// The default [`Comparator`].
+///
+/// Files are considered equal if they are the same inode, or if they have the same length and
+/// [`blake3`] hash. Computed hashes are available as [`PathInfo::hash`].
+///
+/// Files in archives which record CRC-32 checksums (like zip files) are considered different
+/// without hashing them if their checksums differ. Files in git trees are compared by their blob
+/// object IDs without hashing them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Blake3Comparator;
+
+impl Comparator for Blake3Comparator {
+    fn is_same(&self, relative: &Path, old: &PathInfo<'_>, new: &PathInfo<'_>) -> Result<bool> {
+        Ok(if old.stat.id.is_some() && old.stat.id == new.stat.id {
+            true
+        } else if old.stat.size != new.stat.size {
+            false
+        } else {
+            match (&old.stat.checksum, &new.stat.checksum) {
+                (Some(Checksum::GitOid(old_oid)), Some(Checksum::GitOid(new_oid))) => {
+                    old_oid == new_oid
+                }
+                (Some(Checksum::Crc32(old_crc)), Some(Checksum::Crc32(new_crc)))
+                    if old_crc != new_crc =>
+                {
+                    false
+                }
+                _ => old.hash_file(relative)? == new.hash_file(relative)?,
+            }
+        })
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(doc)]
+use crate::DiffTag;
+
+/// Which metadata attributes are compared, in addition to contents, to decide if a path has
+/// changed.
+///
+/// By default, no metadata is compared. Paths with changed metadata are
+/// [`DiffTag::Replace`]d, and the changed attributes are available as
+/// [`crate::DiffEntry::changes`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompareMetadata {
+    pub(crate) mode: bool,
+    pub(crate) owner: bool,
+    pub(crate) mtime: bool,
+}
+
+impl CompareMetadata {
+    /// Create a new [`CompareMetadata`] which compares no metadata.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Compare all supported metadata attributes.
+    pub fn all() -> Self {
+        Self {
+            mode: true,
+            owner: true,
+            mtime: true,
+        }
+    }
+
+    /// Whether to compare permission bits, including the setuid, setgid, and sticky bits.
+    pub fn mode(self, mode: bool) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Whether to compare the owning user and group IDs.
+    pub fn owner(self, owner: bool) -> Self {
+        Self { owner, ..self }
+    }
+
+    /// Whether to compare modification times.
+    pub fn mtime(self, mtime: bool) -> Self {
+        Self { mtime, ..self }
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use similar::TextDiff;
+
+use crate::PathInfo;
+use crate::Result;
+
+/// Files with a NUL byte in this many leading bytes are considered binary, like Git does.
+const BINARY_DETECTION_LEN: usize = 8000;
+
+/// A diff of the contents of a [`crate::DiffTag::Replace`]d file.
+///
+/// Only produced when [`crate::DiffOpts::text_diff`] is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ContentDiff {
+    /// A line-based unified diff of the old and new contents, without a `---`/`+++` header.
+    Text(String),
+    /// At least one of the files is binary, so no text diff was produced.
+    Binary,
+}
+
+/// Diff the contents of the file at `relative` in the old and new trees.
+///
+/// Returns `None` if either tree doesn't record file contents.
+pub(crate) fn content_diff(
+    relative: &Path,
+    old: &PathInfo<'_>,
+    new: &PathInfo<'_>,
+    context_lines: usize,
+) -> Result<Option<ContentDiff>> {
+    let (Some(old), Some(new)) = (old.read(relative)?, new.read(relative)?) else {
+        return Ok(None);
+    };
+
+    let (Some(old), Some(new)) = (as_text(&old), as_text(&new)) else {
+        return Ok(Some(ContentDiff::Binary));
+    };
+
+    Ok(Some(ContentDiff::Text(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(context_lines)
+            .to_string(),
+    )))
+}
+
+fn as_text(contents: &[u8]) -> Option<&str> {
+    let prefix = &contents[..contents.len().min(BINARY_DETECTION_LEN)];
+    if prefix.contains(&0) {
+        None
+    } else {
+        std::str::from_utf8(contents).ok()
+    }
+}
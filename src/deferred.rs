@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+
+use crate::ContentDiff;
+use crate::DiffEntry;
+use crate::DiffOpts;
+use crate::DiffTag;
+use crate::Result;
+use crate::content_diff::content_diff;
+use crate::error_sink::ErrorSink;
+
+/// Entries held back until files present in both trees are compared in parallel.
+///
+/// See [`DiffOpts::parallel_compare`].
+#[derive(Debug, Default)]
+pub(crate) struct Deferred<'a> {
+    /// Every entry, in the order they were produced.
+    entries: Vec<DiffEntry<'a>>,
+    /// Indices in `entries` of files which haven't been compared yet, and the number of errors
+    /// which had been recorded when they were produced.
+    pending: Vec<(usize, usize)>,
+}
+
+impl<'a> Deferred<'a> {
+    pub(crate) fn push(&mut self, entry: DiffEntry<'a>) {
+        self.entries.push(entry);
+    }
+
+    /// Add an entry for a file which still needs to be compared.
+    ///
+    /// Its content is assumed to be unchanged until it's compared. `errors` is the number of
+    /// errors recorded so far, so that errors comparing the file can be put in the same place.
+    pub(crate) fn push_pending(&mut self, entry: DiffEntry<'a>, errors: usize) {
+        self.pending.push((self.entries.len(), errors));
+        self.entries.push(entry);
+    }
+
+    /// Add the entries from another [`Deferred`] after this one's, where `errors` is the number
+    /// of errors recorded before the other [`Deferred`]'s errors.
+    pub(crate) fn append(&mut self, other: Deferred<'a>, errors: usize) {
+        let offset = self.entries.len();
+        self.pending.extend(
+            other
+                .pending
+                .into_iter()
+                .map(|(index, position)| (index + offset, position + errors)),
+        );
+        self.entries.extend(other.entries);
+    }
+
+    /// Compare the pending files and update the entries (and their parent directories) to match.
+    pub(crate) fn compare(
+        self,
+        opts: &DiffOpts,
+        errors: &mut ErrorSink,
+    ) -> Result<Vec<DiffEntry<'a>>> {
+        let Self {
+            mut entries,
+            pending,
+        } = self;
+
+        let results = pending
+            .par_iter()
+            .map(|&(index, _)| compare(&entries[index], opts))
+            .collect::<Vec<_>>();
+
+        // Errors are recovered in order, so the results don't depend on scheduling. Each file's
+        // errors go where they would have if it had been compared during the walk.
+        let mut walk_errors = std::mem::take(&mut errors.errors)
+            .into_iter()
+            .enumerate()
+            .peekable();
+        for ((index, position), (is_same, diff)) in pending.into_iter().zip(results) {
+            while let Some((_, err)) = walk_errors.next_if(|(i, _)| *i < position) {
+                errors.errors.push(err);
+            }
+            let is_same = errors.recover(is_same)?.unwrap_or(false);
+            let diff = match diff {
+                Some(diff) => errors.recover(diff)?.flatten(),
+                None => None,
+            };
+            let entry = &mut entries[index];
+            entry.changes.content = !is_same;
+            if !entry.changes.is_empty() {
+                entry.tag = DiffTag::Replace;
+            }
+            entry.content_diff = diff;
+        }
+        errors.errors.extend(walk_errors.map(|(_, err)| err));
+
+        // Directories are produced after their contents, so changes can be propagated to parent
+        // directories in a single pass.
+        let mut changed_dirs = HashSet::<PathBuf>::new();
+        for entry in &mut entries {
+            if matches!(entry.tag, DiffTag::Equal | DiffTag::Replace)
+                && entry.is_dir()
+                && changed_dirs.contains(&entry.relative)
+            {
+                entry.changes.content = true;
+                entry.tag = DiffTag::Replace;
+            }
+            if entry.tag != DiffTag::Equal
+                && let Some(parent) = entry.relative.parent()
+            {
+                changed_dirs.insert(parent.to_owned());
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Compare a pending file, and diff its contents if it changed.
+fn compare(
+    entry: &DiffEntry<'_>,
+    opts: &DiffOpts,
+) -> (Result<bool>, Option<Result<Option<ContentDiff>>>) {
+    let (Some(deleted), Some(inserted)) = (&entry.deleted, &entry.inserted) else {
+        unreachable!("Pending entries are present in both trees");
+    };
+
+    let is_same = opts.comparator.is_same(&entry.relative, deleted, inserted);
+
+    // If we can't compare the files, we can't say they're the same.
+    let diff = (opts.text_diff
+        && !matches!(is_same, Ok(true))
+        && deleted.stat.is_file()
+        && inserted.stat.is_file())
+    .then(|| content_diff(&entry.relative, deleted, inserted, opts.context_lines));
+
+    (is_same, diff)
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use iddqd::IdOrdMap;
+
+use crate::DiffEntry;
+use crate::DiffTag;
+use crate::PathInfo;
+use crate::Result;
+use crate::candidate_changes::metadata_changes;
+use crate::walk::Side;
+use crate::walk::Walk;
+
+/// The kind of a path, and the length of files.
+///
+/// Only paths of the same kind are considered renames of each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Dir,
+    Symlink,
+    File(u64),
+}
+
+/// Match [`DiffTag::Delete`]d entries with [`DiffTag::Insert`]ed entries with the same contents
+/// and replace each pair with a single [`DiffTag::Rename`] entry.
+///
+/// Empty files and directories are never considered renamed, because any one of them is as good
+/// a match as any other.
+pub(crate) fn detect_renames(
+    entries: &mut IdOrdMap<DiffEntry<'_>>,
+    walk: &mut Walk<'_, '_>,
+) -> Result<()> {
+    let candidates = |tag: DiffTag| {
+        entries
+            .iter()
+            .filter(move |entry| entry.tag == tag)
+            .filter_map(|entry| {
+                let info = entry.deleted.as_ref().or(entry.inserted.as_ref())?;
+                let kind = if info.stat.is_dir() {
+                    Kind::Dir
+                } else if info.stat.is_symlink() {
+                    Kind::Symlink
+                } else {
+                    Kind::File(info.stat.size)
+                };
+                (kind != Kind::File(0)).then_some((entry.relative.as_path(), info, kind))
+            })
+            .collect::<Vec<_>>()
+    };
+    let deleted = candidates(DiffTag::Delete);
+    let inserted = candidates(DiffTag::Insert);
+
+    // Only hash paths which could possibly have a match on the other side.
+    let deleted_kinds = deleted
+        .iter()
+        .map(|(_, _, kind)| *kind)
+        .collect::<HashSet<_>>();
+    let inserted_kinds = inserted
+        .iter()
+        .map(|(_, _, kind)| *kind)
+        .collect::<HashSet<_>>();
+
+    let mut by_hash = HashMap::<(Kind, blake3::Hash), VecDeque<PathBuf>>::new();
+    for (relative, info, kind) in deleted {
+        if inserted_kinds.contains(&kind)
+            && let Some(Some(hash)) = content_hash(walk, Side::Old, relative, info)?
+        {
+            by_hash
+                .entry((kind, hash))
+                .or_default()
+                .push_back(relative.to_owned());
+        }
+    }
+
+    let mut renames = Vec::new();
+    for (relative, info, kind) in inserted {
+        if deleted_kinds.contains(&kind)
+            && let Some(Some(hash)) = content_hash(walk, Side::New, relative, info)?
+            && let Some(from) = by_hash.get_mut(&(kind, hash)).and_then(VecDeque::pop_front)
+        {
+            renames.push((from, relative.to_owned()));
+        }
+    }
+
+    for (from, to) in renames {
+        let removed = entries.remove(from.as_path());
+        let added = entries.remove(to.as_path());
+        if let (Some(removed), Some(added)) = (removed, added) {
+            tracing::debug!(?from, ?to, "Detected rename");
+            // The contents are the same, but the metadata may not be.
+            let changes = match (&removed.deleted, &added.inserted) {
+                (Some(deleted), Some(inserted)) => metadata_changes(deleted, inserted, walk.opts),
+                _ => Default::default(),
+            };
+            entries.insert_overwrite(DiffEntry {
+                relative: to,
+                tag: DiffTag::Rename,
+                changes,
+                renamed_from: Some(from),
+                content_diff: None,
+                assumed_equal: false,
+                deleted: removed.deleted,
+                inserted: added.inserted,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `None` if the hash couldn't be computed and the error was recovered from.
+fn content_hash(
+    walk: &mut Walk<'_, '_>,
+    side: Side,
+    relative: &Path,
+    info: &PathInfo<'_>,
+) -> Result<Option<Option<blake3::Hash>>> {
+    if info.stat.is_dir() {
+        return walk.hash_tree(side, relative).map(Some);
+    }
+
+    let hash = if info.stat.is_symlink() {
+        info.tree
+            .read_link(relative)
+            .map(|target| Some(blake3::hash(target.as_os_str().as_bytes())))
+    } else {
+        info.hash_file(relative).map(Some)
+    };
+    walk.errors.recover(hash)
+}
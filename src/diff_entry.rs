@@ -5,15 +5,22 @@ use iddqd::IdOrdItem;
 use iddqd::id_upcast;
 use owo_colors::Style;
 
+use crate::Changes;
+use crate::ContentDiff;
 use crate::DiffTag;
 use crate::DisplayDiffOpts;
 use crate::PathInfo;
+use crate::base::Base;
 
 /// A single entry in a diff, identified by a path relative to the diff base directory.
 #[derive(Debug, Clone)]
 pub struct DiffEntry<'a> {
     pub(crate) relative: PathBuf,
     pub(crate) tag: DiffTag,
+    pub(crate) changes: Changes,
+    pub(crate) renamed_from: Option<PathBuf>,
+    pub(crate) content_diff: Option<ContentDiff>,
+    pub(crate) assumed_equal: bool,
     pub(crate) deleted: Option<PathInfo<'a>>,
     pub(crate) inserted: Option<PathInfo<'a>>,
 }
@@ -42,7 +49,38 @@ impl<'a> DiffEntry<'a> {
         self.tag
     }
 
+    /// For [`DiffTag::Replace`]d and [`DiffTag::TypeChange`]d entries, which attributes of the
+    /// path changed. For [`DiffTag::Rename`]d entries, which metadata attributes changed.
+    ///
+    /// This is empty for other entries.
+    pub fn changes(&self) -> Changes {
+        self.changes
+    }
+
+    /// For [`DiffTag::Rename`]d entries, the path this entry was moved from, relative to the old
+    /// path being diffed.
+    pub fn renamed_from(&self) -> Option<&Path> {
+        self.renamed_from.as_deref()
+    }
+
+    /// For [`DiffTag::Replace`]d files, a diff of the old and new contents.
+    ///
+    /// Only present if [`crate::DiffOpts::text_diff`] is enabled.
+    pub fn content_diff(&self) -> Option<&ContentDiff> {
+        self.content_diff.as_ref()
+    }
+
+    /// Whether this file's contents were assumed to be unchanged because its size and
+    /// modification time didn't change, rather than being compared.
+    ///
+    /// This is only ever `true` if [`crate::DiffOpts::quick_check`] is enabled.
+    pub fn assumed_equal(&self) -> bool {
+        self.assumed_equal
+    }
+
     /// Information for the old path, if any.
+    ///
+    /// For [`DiffTag::Rename`]d entries, this is the information for [`DiffEntry::renamed_from`].
     pub fn deleted(&self) -> Option<&PathInfo<'a>> {
         self.deleted.as_ref()
     }
@@ -52,24 +90,83 @@ impl<'a> DiffEntry<'a> {
         self.inserted.as_ref()
     }
 
+    /// Convert this into a [`DiffEntry`] which owns its [`PathInfo::base`]s.
+    ///
+    /// Prefer [`crate::Diff::into_owned`] when converting a whole diff, which shares the base
+    /// paths between entries.
+    pub fn into_owned(self) -> DiffEntry<'static> {
+        DiffEntry {
+            relative: self.relative,
+            tag: self.tag,
+            changes: self.changes,
+            renamed_from: self.renamed_from,
+            content_diff: self.content_diff,
+            assumed_equal: self.assumed_equal,
+            deleted: self.deleted.map(PathInfo::into_owned),
+            inserted: self.inserted.map(PathInfo::into_owned),
+        }
+    }
+
+    /// Replace the [`PathInfo::base`]s of this entry.
+    pub(crate) fn with_bases<'b>(self, old: &Base<'b>, new: &Base<'b>) -> DiffEntry<'b> {
+        DiffEntry {
+            relative: self.relative,
+            tag: self.tag,
+            changes: self.changes,
+            renamed_from: self.renamed_from,
+            content_diff: self.content_diff,
+            assumed_equal: self.assumed_equal,
+            deleted: self.deleted.map(|info| info.with_base(old.clone())),
+            inserted: self.inserted.map(|info| info.with_base(new.clone())),
+        }
+    }
+
+    pub(crate) fn new(
+        relative: PathBuf,
+        tag: DiffTag,
+        deleted: Option<PathInfo<'a>>,
+        inserted: Option<PathInfo<'a>>,
+    ) -> Self {
+        Self {
+            relative,
+            tag,
+            changes: Default::default(),
+            renamed_from: None,
+            content_diff: None,
+            assumed_equal: false,
+            deleted,
+            inserted,
+        }
+    }
+
     pub(crate) fn is_dir(&self) -> bool {
         self.inserted
             .as_ref()
             .or(self.deleted.as_ref())
-            .map(|info| info.metadata.is_dir())
+            .map(|info| info.stat.is_dir())
             .unwrap_or(false)
     }
 
     pub(crate) fn format_path(&self) -> String {
-        let mut ret = self.relative.display().to_string();
-        if self.is_dir() {
-            ret.push('/');
+        let format = |path: &Path| {
+            let mut ret = path.display().to_string();
+            if self.is_dir() {
+                ret.push('/');
+            }
+            ret
+        };
+        match &self.renamed_from {
+            Some(from) => format!("{} -> {}", format(from), format(&self.relative)),
+            None => format(&self.relative),
         }
-        ret
     }
 
     fn styled(&self, style: Style) -> owo_colors::Styled<String> {
-        style.style(format!("{} {}", self.tag.marker(), self.format_path()))
+        let mut line = format!("{} {}", self.tag.marker(), self.format_path());
+        if self.changes.metadata() {
+            line.push_str(&format!(" ({})", self.changes));
+        }
+        style.style(line)
     }
 
     pub(crate) fn fmt_with(
@@ -89,13 +186,16 @@ impl<'a> DiffEntry<'a> {
                 writeln!(f, "{}", self.styled(style))?;
             }
             DiffTag::Replace => {
-                // Directory entries are not very useful for me. This should probably also be
-                // customizable.
-                if !self.is_dir() {
+                // Changed directories are implied by their changed contents, so they're not very
+                // useful to display. This should probably also be customizable.
+                if !self.is_dir() || self.changes.metadata() {
                     writeln!(f, "{}", self.styled(style))?;
                 }
+                if let Some(content_diff) = &self.content_diff {
+                    fmt_content_diff(f, content_diff, opts)?;
+                }
             }
-            DiffTag::Insert => {
+            DiffTag::Insert | DiffTag::Rename | DiffTag::TypeChange => {
                 writeln!(f, "{}", self.styled(style))?;
             }
         }
@@ -107,3 +207,32 @@ impl<'a> DiffEntry<'a> {
         (self.relative(), self.tag())
     }
 }
+
+fn fmt_content_diff(
+    f: &mut std::fmt::Formatter<'_>,
+    content_diff: &ContentDiff,
+    opts: &DisplayDiffOpts,
+) -> std::fmt::Result {
+    match content_diff {
+        ContentDiff::Text(text) => {
+            for line in text.lines() {
+                let style = if !opts.color {
+                    Style::new()
+                } else if line.starts_with("@@") {
+                    Style::new().cyan()
+                } else if line.starts_with('-') {
+                    DiffTag::Delete.style()
+                } else if line.starts_with('+') {
+                    DiffTag::Insert.style()
+                } else {
+                    Style::new()
+                };
+                writeln!(f, "{}", style.style(line))?;
+            }
+        }
+        ContentDiff::Binary => {
+            writeln!(f, "Binary files differ")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::Blake3Comparator;
+use crate::Comparator;
+use crate::CompareMetadata;
+#[cfg(doc)]
+use crate::Diff;
+#[cfg(doc)]
+use crate::DiffTag;
+use crate::HashCache;
+
+/// Options for constructing a [`Diff`].
+#[derive(Clone)]
+pub struct DiffOpts {
+    pub(crate) comparator: Arc<dyn Comparator + Send + Sync>,
+    pub(crate) compare_metadata: CompareMetadata,
+    pub(crate) follow_links: bool,
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+    pub(crate) ignore_files: bool,
+    pub(crate) custom_ignore_filenames: Vec<String>,
+    pub(crate) tolerate_errors: bool,
+    pub(crate) detect_renames: bool,
+    pub(crate) text_diff: bool,
+    pub(crate) context_lines: usize,
+    pub(crate) parallel_compare: bool,
+    pub(crate) parallel_walk: bool,
+    pub(crate) hash_cache: Option<HashCache>,
+    pub(crate) quick_check: bool,
+}
+
+impl Default for DiffOpts {
+    fn default() -> Self {
+        Self {
+            comparator: Arc::new(Blake3Comparator),
+            compare_metadata: Default::default(),
+            follow_links: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_files: false,
+            custom_ignore_filenames: Vec::new(),
+            tolerate_errors: false,
+            detect_renames: false,
+            text_diff: false,
+            context_lines: 3,
+            parallel_compare: false,
+            parallel_walk: false,
+            hash_cache: None,
+            quick_check: false,
+        }
+    }
+}
+
+impl Debug for DiffOpts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiffOpts")
+            .field("compare_metadata", &self.compare_metadata)
+            .field("follow_links", &self.follow_links)
+            .field("include", &self.include)
+            .field("exclude", &self.exclude)
+            .field("ignore_files", &self.ignore_files)
+            .field("custom_ignore_filenames", &self.custom_ignore_filenames)
+            .field("tolerate_errors", &self.tolerate_errors)
+            .field("detect_renames", &self.detect_renames)
+            .field("text_diff", &self.text_diff)
+            .field("context_lines", &self.context_lines)
+            .field("parallel_compare", &self.parallel_compare)
+            .field("parallel_walk", &self.parallel_walk)
+            .field("hash_cache", &self.hash_cache)
+            .field("quick_check", &self.quick_check)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DiffOpts {
+    /// Create a new default [`DiffOpts`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The [`Comparator`] used to decide if files present in both trees are equal.
+    ///
+    /// Defaults to [`Blake3Comparator`].
+    pub fn comparator(self, comparator: impl Comparator + Send + Sync + 'static) -> Self {
+        Self {
+            comparator: Arc::new(comparator),
+            ..self
+        }
+    }
+
+    /// Which metadata attributes to compare, in addition to file contents.
+    ///
+    /// Defaults to [`CompareMetadata::new`], which compares no metadata.
+    pub fn compare_metadata(self, compare_metadata: CompareMetadata) -> Self {
+        Self {
+            compare_metadata,
+            ..self
+        }
+    }
+
+    /// Whether to follow symbolic links.
+    ///
+    /// When enabled, symbolic links are compared by the contents of the paths they point to.
+    /// When disabled, symbolic links are compared by their targets, so a link which points to a
+    /// different path is changed even if both paths have the same contents.
+    ///
+    /// This only applies to directories on the local filesystem. Symbolic links in other
+    /// [`crate::Source`]s (like manifests and archives) are never followed, so you'll usually
+    /// want to disable this when diffing a directory against one of them.
+    ///
+    /// Defaults to `true`.
+    pub fn follow_links(self, follow_links: bool) -> Self {
+        Self {
+            follow_links,
+            ..self
+        }
+    }
+
+    /// Only diff files matching the given glob pattern.
+    ///
+    /// Patterns are matched against both the path relative to the trees being diffed and the
+    /// file name, so `*.rs` matches Rust files at any depth. A file is included if it or any of
+    /// its parent directories match any include pattern. If no include patterns are given, all
+    /// files are included.
+    ///
+    /// Directories are always walked (unless they're excluded), because they may contain included
+    /// files.
+    ///
+    /// Invalid patterns are reported as [`crate::Error::Glob`] when the [`Diff`] is constructed.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Don't diff paths matching the given glob pattern.
+    ///
+    /// Patterns are matched like [`DiffOpts::include`]. Excluded directories are not traversed,
+    /// and exclude patterns take priority over include patterns.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Whether to respect `.gitignore` and `.ignore` files.
+    ///
+    /// Each tree is walked with its own ignore files, which are respected regardless of whether
+    /// the tree is a Git repository. Ignored directories are not traversed. Files in
+    /// subdirectories take priority over files in their parents, and `.ignore` files take
+    /// priority over `.gitignore` files.
+    ///
+    /// Defaults to `false`.
+    pub fn ignore_files(self, ignore_files: bool) -> Self {
+        Self {
+            ignore_files,
+            ..self
+        }
+    }
+
+    /// Respect ignore files with the given name, in the same format as `.gitignore` files.
+    ///
+    /// Custom ignore files are respected even if [`DiffOpts::ignore_files`] is disabled, and take
+    /// priority over `.gitignore` and `.ignore` files.
+    pub fn custom_ignore_filename(mut self, filename: impl Into<String>) -> Self {
+        self.custom_ignore_filenames.push(filename.into());
+        self
+    }
+
+    /// Whether to keep diffing when individual paths can't be read.
+    ///
+    /// When enabled, errors for individual paths (like permission errors, paths which are
+    /// deleted while the trees are being walked, or I/O errors while hashing) are collected in
+    /// [`Diff::errors`] rather than aborting the diff. Paths which can't be compared are
+    /// [`DiffTag::Replace`]d, and paths which can't be read at all are omitted from the diff.
+    ///
+    /// Defaults to `false`.
+    pub fn tolerate_errors(self, tolerate_errors: bool) -> Self {
+        Self {
+            tolerate_errors,
+            ..self
+        }
+    }
+
+    /// Whether to detect renamed files and directories.
+    ///
+    /// When enabled, [`DiffTag::Delete`]d and [`DiffTag::Insert`]ed entries with the same
+    /// [`blake3`] hash are combined into a single [`DiffTag::Rename`] entry. Directories are
+    /// renamed only if their entire contents are unchanged.
+    ///
+    /// Defaults to `false`.
+    pub fn detect_renames(self, detect_renames: bool) -> Self {
+        Self {
+            detect_renames,
+            ..self
+        }
+    }
+
+    /// Whether to produce a unified diff of the contents of [`DiffTag::Replace`]d files.
+    ///
+    /// The diffs are available as [`crate::DiffEntry::content_diff`] and are included when the
+    /// [`Diff`] is displayed. Binary files are detected and are not diffed.
+    ///
+    /// Defaults to `false`.
+    pub fn text_diff(self, text_diff: bool) -> Self {
+        Self { text_diff, ..self }
+    }
+
+    /// The number of lines of context to include around each change in a
+    /// [`DiffOpts::text_diff`].
+    ///
+    /// Defaults to 3.
+    pub fn context_lines(self, context_lines: usize) -> Self {
+        Self {
+            context_lines,
+            ..self
+        }
+    }
+
+    /// Whether to compare files present in both trees in parallel.
+    ///
+    /// When enabled, files which need to be compared with the [`DiffOpts::comparator`] are
+    /// collected while the trees are walked, and are then compared (and hashed) on a [`rayon`]
+    /// thread pool. The resulting entries and errors are the same as when comparing sequentially,
+    /// but [`Diff::stream`] won't produce any entries until every file has been compared.
+    ///
+    /// Defaults to `false`.
+    pub fn parallel_compare(self, parallel_compare: bool) -> Self {
+        Self {
+            parallel_compare,
+            ..self
+        }
+    }
+
+    /// Whether to walk the trees in parallel.
+    ///
+    /// When enabled, the contents of each directory are listed and diffed concurrently on a
+    /// [`rayon`] thread pool, which helps with wide trees and slow (e.g. network) filesystems.
+    /// Results from each directory are collected and produced in the same order as when walking
+    /// sequentially, so the resulting entries and errors don't depend on thread scheduling.
+    ///
+    /// Defaults to `false`.
+    pub fn parallel_walk(self, parallel_walk: bool) -> Self {
+        Self {
+            parallel_walk,
+            ..self
+        }
+    }
+
+    /// A [`HashCache`] to consult before hashing files, and to update afterwards.
+    ///
+    /// The cache is used by [`Blake3Comparator`] and when detecting renames. Call
+    /// [`HashCache::save`] after diffing to persist any new hashes.
+    pub fn hash_cache(self, hash_cache: HashCache) -> Self {
+        Self {
+            hash_cache: Some(hash_cache),
+            ..self
+        }
+    }
+
+    /// Whether to assume files are unchanged if their size and modification time are the same,
+    /// like `rsync`'s quick check.
+    ///
+    /// This is much faster, because unchanged files are never read, but can miss changes which
+    /// don't affect a file's size or modification time. Files which are assumed to be unchanged
+    /// are marked with [`crate::DiffEntry::assumed_equal`]. Files with a different size or
+    /// modification time are still compared with the [`DiffOpts::comparator`].
+    ///
+    /// Defaults to `false`.
+    pub fn quick_check(self, quick_check: bool) -> Self {
+        Self {
+            quick_check,
+            ..self
+        }
+    }
+}
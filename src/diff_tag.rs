@@ -4,15 +4,35 @@ use owo_colors::Style;
 ///
 /// [1]: https://docs.rs/similar/latest/similar/enum.DiffTag.html
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum DiffTag {
     /// An entry that is equal in both sides of the diff.
     Equal,
     /// An entry that is present in the 'old' side of the diff and absent in the 'new' side.
     Delete,
     /// An entry that is present in both sides of the diff, but with changed contents.
+    ///
+    /// Directories are replaced if any of their contents have changed.
     Replace,
     /// An entry that is absent in the 'old' side of the diff and present in the 'new' side.
     Insert,
+    /// An entry that was moved from another path in the 'old' side of the diff to this path in
+    /// the 'new' side, without changing its contents.
+    ///
+    /// Only produced when [`crate::DiffOpts::detect_renames`] is enabled. The original path is
+    /// available as [`crate::DiffEntry::renamed_from`].
+    Rename,
+    /// An entry that is present in both sides of the diff with a different file type, like a
+    /// file which was replaced with a directory or a symbolic link.
+    ///
+    /// The contents of a directory which was replaced are reported as [`DiffTag::Delete`]d, and
+    /// the contents of a directory which replaced another path are reported as
+    /// [`DiffTag::Insert`]ed.
+    TypeChange,
 }
 
 impl DiffTag {
@@ -23,6 +43,8 @@ impl DiffTag {
             DiffTag::Delete => Style::new().red(),
             DiffTag::Replace => Style::new().yellow(),
             DiffTag::Insert => Style::new().green(),
+            DiffTag::Rename => Style::new().cyan(),
+            DiffTag::TypeChange => Style::new().magenta(),
         }
     }
 
@@ -33,6 +55,8 @@ impl DiffTag {
             DiffTag::Delete => '-',
             DiffTag::Replace => '~',
             DiffTag::Insert => '+',
+            DiffTag::Rename => '>',
+            DiffTag::TypeChange => '!',
         }
     }
 }
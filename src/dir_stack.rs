@@ -0,0 +1,115 @@
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use ignore::Match;
+use ignore::gitignore::Gitignore;
+use ignore::gitignore::GitignoreBuilder;
+
+use crate::stat::Stat;
+use crate::tree::Tree;
+
+/// The directories containing the path currently being walked in one tree.
+///
+/// This is used to respect ignore files and to detect symbolic link loops.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirStack {
+    top: Option<Arc<Dir>>,
+}
+
+#[derive(Debug)]
+struct Dir {
+    parent: DirStack,
+    /// The device and inode numbers of the directory, if known.
+    id: Option<(u64, u64)>,
+    /// The ignore files in the directory, if any.
+    ignore: Option<Gitignore>,
+}
+
+impl DirStack {
+    /// Enter the directory at `relative`, loading the ignore files with the given names.
+    pub(crate) fn push(
+        &self,
+        tree: &dyn Tree,
+        relative: &Path,
+        stat: &Stat,
+        ignore_filenames: &[String],
+    ) -> Self {
+        Self {
+            top: Some(Arc::new(Dir {
+                parent: self.clone(),
+                id: stat.id,
+                ignore: load_ignore_files(tree, relative, ignore_filenames),
+            })),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Dir> {
+        std::iter::successors(self.top.as_deref(), |dir| dir.parent.top.as_deref())
+    }
+
+    /// Is the directory with the given metadata already on the stack?
+    ///
+    /// This indicates a symbolic link loop.
+    pub(crate) fn contains(&self, stat: &Stat) -> bool {
+        stat.id.is_some() && self.iter().any(|dir| dir.id == stat.id)
+    }
+
+    /// Is the given path, which is a child of the top directory, ignored?
+    pub(crate) fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        // Ignore files in deeper directories take priority.
+        for gitignore in self.iter().filter_map(|dir| dir.ignore.as_ref()) {
+            match gitignore.matched(relative, is_dir) {
+                Match::None => {}
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+}
+
+fn load_ignore_files(tree: &dyn Tree, dir: &Path, filenames: &[String]) -> Option<Gitignore> {
+    if filenames.is_empty() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    // Later files take priority over earlier ones.
+    for filename in filenames {
+        let relative = dir.join(filename);
+        if !tree.stat(&relative).is_ok_and(|stat| stat.is_file()) {
+            continue;
+        }
+
+        let mut contents = String::new();
+        let read = tree
+            .open(&relative)
+            .map_err(|err| err.to_string())
+            .and_then(|reader| match reader {
+                Some(mut reader) => reader
+                    .read_to_string(&mut contents)
+                    .map_err(|err| err.to_string()),
+                None => Ok(0),
+            });
+        if let Err(err) = read {
+            tracing::warn!("Failed to read ignore file {relative:?}: {err}");
+            continue;
+        }
+
+        let from = tree.root().join(&relative);
+        for line in contents.lines() {
+            if let Err(err) = builder.add_line(Some(from.clone()), line) {
+                tracing::warn!("Invalid line in ignore file {from:?}: {err}");
+            }
+        }
+    }
+
+    match builder.build() {
+        Ok(gitignore) => (!gitignore.is_empty()).then_some(gitignore),
+        Err(err) => {
+            tracing::warn!("Failed to read ignore files in {dir:?}: {err}");
+            None
+        }
+    }
+}
@@ -21,6 +21,7 @@ impl<'a> Display for DisplayDiff<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::DiffOpts;
     use crate::Result;
     use indoc::indoc;
     use testlib::TempTree;
@@ -83,4 +84,43 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_display_text_diff() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "a\nb\nc\nd\ne\n")
+            .unwrap()
+            .file("binary", "\0puppy")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "a\nb\nx\nd\ne\n")
+            .unwrap()
+            .file("binary", "\0doggy")
+            .unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().text_diff(true).context_lines(1),
+        )?;
+
+        assert_eq!(
+            diff.to_string(),
+            indoc!(
+                r#"
+                ~ binary
+                Binary files differ
+                ~ puppy
+                @@ -2,3 +2,3 @@
+                 b
+                -c
+                +x
+                 d
+                "#
+            )
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,19 @@
+use std::fmt::Display;
+
+use crate::ThreeWayDiff;
+use crate::display_diff_opts::DisplayDiffOpts;
+
+/// A [`Display`]able [`ThreeWayDiff`] combined with [`DisplayDiffOpts`].
+pub struct DisplayThreeWayDiff<'a> {
+    pub(crate) diff: &'a ThreeWayDiff<'a>,
+    pub(crate) opts: DisplayDiffOpts,
+}
+
+impl<'a> Display for DisplayThreeWayDiff<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.diff.entries {
+            entry.fmt_with(f, &self.opts)?;
+        }
+        Ok(())
+    }
+}
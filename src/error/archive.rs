@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while reading an archive, like a tarball.
+#[derive(Debug)]
+pub struct ArchiveError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: std::io::Error,
+}
+
+impl ArchiveError {
+    /// The path of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to read archive `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while reading a tree from a git repository.
+#[derive(Debug)]
+pub struct GitError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl GitError {
+    /// The path of the repository.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for GitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to read git repository `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for GitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.inner.as_ref())
+    }
+}
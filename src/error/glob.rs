@@ -0,0 +1,27 @@
+use std::fmt::Display;
+
+/// An error encountered while parsing an include or exclude glob pattern.
+#[derive(Debug)]
+pub struct GlobError {
+    pub(crate) pattern: String,
+    pub(crate) inner: globset::Error,
+}
+
+impl GlobError {
+    /// The pattern that caused this error.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl Display for GlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid glob pattern `{}`: {}", self.pattern, self.inner)
+    }
+}
+
+impl std::error::Error for GlobError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
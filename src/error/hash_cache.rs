@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while loading or saving a [`crate::HashCache`].
+#[derive(Debug)]
+pub struct HashCacheError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: std::io::Error,
+}
+
+impl HashCacheError {
+    /// The path of the cache file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for HashCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to access hash cache `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for HashCacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
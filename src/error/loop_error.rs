@@ -0,0 +1,28 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered when following a symbolic link to one of its own parent directories.
+#[derive(Debug)]
+pub struct LoopError {
+    pub(crate) path: PathBuf,
+}
+
+impl LoopError {
+    /// The path that caused this error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for LoopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Symbolic link loop detected at `{}`",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for LoopError {}
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while parsing a [`crate::Manifest`].
+#[derive(Debug)]
+pub struct ManifestError {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+impl ManifestError {
+    /// The path of the manifest, if it was loaded from a file.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The line number the error occurred on, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(
+                f,
+                "Invalid manifest `{}` on line {}: {}",
+                path.display(),
+                self.line,
+                self.message
+            ),
+            None => write!(
+                f,
+                "Invalid manifest on line {}: {}",
+                self.line, self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
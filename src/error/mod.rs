@@ -1,16 +1,40 @@
 use std::fmt::Display;
 
+mod archive;
+mod compare;
+mod git;
+mod glob;
 mod hash;
+mod hash_cache;
+mod loop_error;
+mod manifest;
 mod metadata;
+mod read;
+mod read_link;
+mod save_manifest;
 mod strip_prefix;
 mod traverse;
+mod tree;
 mod walkdir_metadata;
+mod write;
 
+pub use archive::ArchiveError;
+pub use compare::CompareError;
+pub use git::GitError;
+pub use glob::GlobError;
 pub use hash::HashError;
+pub use hash_cache::HashCacheError;
+pub use loop_error::LoopError;
+pub use manifest::ManifestError;
 pub use metadata::MetadataError;
+pub use read::ReadError;
+pub use read_link::ReadLinkError;
+pub use save_manifest::SaveManifestError;
 pub use strip_prefix::StripPrefixError;
 pub use traverse::TraverseError;
+pub use tree::TreeError;
 pub use walkdir_metadata::WalkDirMetadataError;
+pub use write::WriteError;
 
 /// An error encountered while diffing two paths.
 #[derive(Debug)]
@@ -26,6 +50,30 @@ pub enum Error {
     Hash(HashError),
     /// An error encountered while removing a prefix from a [`Path`].
     StripPrefix(StripPrefixError),
+    /// An error encountered by a [`crate::Comparator`] while comparing two files.
+    Compare(CompareError),
+    /// An error encountered while reading a file to produce a text diff.
+    Read(ReadError),
+    /// An error encountered while reading the target of a symbolic link.
+    ReadLink(ReadLinkError),
+    /// An error encountered while parsing an include or exclude glob pattern.
+    Glob(GlobError),
+    /// An error encountered when following a symbolic link to one of its own parent directories.
+    Loop(LoopError),
+    /// An error encountered while writing a streamed diff.
+    Write(WriteError),
+    /// An error encountered while loading or saving a [`crate::HashCache`].
+    HashCache(HashCacheError),
+    /// An error encountered while parsing a [`crate::Manifest`].
+    Manifest(ManifestError),
+    /// An error encountered while saving a [`crate::Manifest`] to a file.
+    SaveManifest(SaveManifestError),
+    /// An error encountered while reading an archive, like a tarball.
+    Archive(ArchiveError),
+    /// An error encountered while reading a tree from a git repository.
+    Git(GitError),
+    /// An error reading a path from a custom [`crate::Tree`].
+    Tree(TreeError),
 }
 
 /// A [`std::result::Result`] produced by diffing two paths.
@@ -39,6 +87,18 @@ impl Display for Error {
             Error::WalkDirMetadata(inner) => inner.fmt(f),
             Error::Hash(inner) => inner.fmt(f),
             Error::StripPrefix(inner) => inner.fmt(f),
+            Error::Compare(inner) => inner.fmt(f),
+            Error::Read(inner) => inner.fmt(f),
+            Error::ReadLink(inner) => inner.fmt(f),
+            Error::Glob(inner) => inner.fmt(f),
+            Error::Loop(inner) => inner.fmt(f),
+            Error::Write(inner) => inner.fmt(f),
+            Error::HashCache(inner) => inner.fmt(f),
+            Error::Manifest(inner) => inner.fmt(f),
+            Error::SaveManifest(inner) => inner.fmt(f),
+            Error::Archive(inner) => inner.fmt(f),
+            Error::Git(inner) => inner.fmt(f),
+            Error::Tree(inner) => inner.fmt(f),
         }
     }
 }
@@ -51,6 +111,18 @@ impl std::error::Error for Error {
             Error::WalkDirMetadata(inner) => inner.source(),
             Error::Hash(inner) => inner.source(),
             Error::StripPrefix(inner) => inner.source(),
+            Error::Compare(inner) => inner.source(),
+            Error::Read(inner) => inner.source(),
+            Error::ReadLink(inner) => inner.source(),
+            Error::Glob(inner) => inner.source(),
+            Error::Loop(inner) => inner.source(),
+            Error::Write(inner) => inner.source(),
+            Error::HashCache(inner) => inner.source(),
+            Error::Manifest(inner) => inner.source(),
+            Error::SaveManifest(inner) => inner.source(),
+            Error::Archive(inner) => inner.source(),
+            Error::Git(inner) => inner.source(),
+            Error::Tree(inner) => inner.source(),
         }
     }
 }
@@ -84,3 +156,75 @@ impl From<StripPrefixError> for Error {
         Self::StripPrefix(value)
     }
 }
+
+impl From<CompareError> for Error {
+    fn from(value: CompareError) -> Self {
+        Self::Compare(value)
+    }
+}
+
+impl From<ReadError> for Error {
+    fn from(value: ReadError) -> Self {
+        Self::Read(value)
+    }
+}
+
+impl From<ReadLinkError> for Error {
+    fn from(value: ReadLinkError) -> Self {
+        Self::ReadLink(value)
+    }
+}
+
+impl From<GlobError> for Error {
+    fn from(value: GlobError) -> Self {
+        Self::Glob(value)
+    }
+}
+
+impl From<LoopError> for Error {
+    fn from(value: LoopError) -> Self {
+        Self::Loop(value)
+    }
+}
+
+impl From<WriteError> for Error {
+    fn from(value: WriteError) -> Self {
+        Self::Write(value)
+    }
+}
+
+impl From<HashCacheError> for Error {
+    fn from(value: HashCacheError) -> Self {
+        Self::HashCache(value)
+    }
+}
+
+impl From<ManifestError> for Error {
+    fn from(value: ManifestError) -> Self {
+        Self::Manifest(value)
+    }
+}
+
+impl From<SaveManifestError> for Error {
+    fn from(value: SaveManifestError) -> Self {
+        Self::SaveManifest(value)
+    }
+}
+
+impl From<ArchiveError> for Error {
+    fn from(value: ArchiveError) -> Self {
+        Self::Archive(value)
+    }
+}
+
+impl From<GitError> for Error {
+    fn from(value: GitError) -> Self {
+        Self::Git(value)
+    }
+}
+
+impl From<TreeError> for Error {
+    fn from(value: TreeError) -> Self {
+        Self::Tree(value)
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while reading a file to produce a text diff.
+#[derive(Debug)]
+pub struct ReadError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: std::io::Error,
+}
+
+impl ReadError {
+    /// The path that caused this error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to read file `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
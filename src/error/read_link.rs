@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while reading the target of a symbolic link.
+#[derive(Debug)]
+pub struct ReadLinkError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: std::io::Error,
+}
+
+impl ReadLinkError {
+    /// The path that caused this error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for ReadLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to read symlink `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for ReadLinkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
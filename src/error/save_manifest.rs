@@ -0,0 +1,34 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered while saving a [`crate::Manifest`] to a file.
+#[derive(Debug)]
+pub struct SaveManifestError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: std::io::Error,
+}
+
+impl SaveManifestError {
+    /// The path the manifest was being saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for SaveManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to save manifest `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for SaveManifestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
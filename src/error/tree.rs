@@ -0,0 +1,45 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error encountered by a custom [`crate::Tree`] while reading a path.
+#[derive(Debug)]
+pub struct TreeError {
+    pub(crate) path: PathBuf,
+    pub(crate) inner: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl TreeError {
+    /// Construct a new error for a custom [`crate::Tree`].
+    pub fn new(
+        path: impl Into<PathBuf>,
+        inner: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            inner: inner.into(),
+        }
+    }
+
+    /// The path that caused this error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Display for TreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to read `{}`: {}",
+            self.path.display(),
+            self.inner
+        )
+    }
+}
+
+impl std::error::Error for TreeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.inner.as_ref())
+    }
+}
@@ -0,0 +1,19 @@
+use std::fmt::Display;
+
+/// An error encountered while writing a streamed diff.
+#[derive(Debug)]
+pub struct WriteError {
+    pub(crate) inner: std::io::Error,
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to write diff: {}", self.inner)
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
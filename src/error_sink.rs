@@ -0,0 +1,32 @@
+use crate::Error;
+use crate::Result;
+
+/// Collects per-path errors when [`crate::DiffOpts::tolerate_errors`] is enabled.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorSink {
+    tolerate: bool,
+    pub(crate) errors: Vec<Error>,
+}
+
+impl ErrorSink {
+    pub(crate) fn new(tolerate: bool) -> Self {
+        Self {
+            tolerate,
+            errors: Vec::new(),
+        }
+    }
+
+    /// If errors are tolerated, record an error and return `Ok(None)`. Otherwise, return the
+    /// error.
+    pub(crate) fn recover<T>(&mut self, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self.tolerate => {
+                tracing::debug!("Recovered from error: {err}");
+                self.errors.push(err);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Escape a path so it can be written on a single line of text.
+///
+/// Backslashes are doubled, and control characters and bytes which aren't valid UTF-8 are
+/// written as `\xNN`. Use [`unescape_path`] to recover the original path.
+pub(crate) fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                c if c.is_control() => {
+                    for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                        let _ = write!(escaped, "\\x{byte:02x}");
+                    }
+                }
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\x{byte:02x}");
+        }
+    }
+    escaped
+}
+
+/// Reverse [`escape_path`].
+///
+/// Returns `None` if `escaped` contains an invalid escape sequence.
+pub(crate) fn unescape_path(escaped: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = match (byte, tail) {
+            (b'\\', [b'\\', tail @ ..]) => {
+                bytes.push(b'\\');
+                tail
+            }
+            (b'\\', [b'x', high, low, tail @ ..])
+                if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+            {
+                let hex = std::str::from_utf8(&[*high, *low]).ok()?.to_owned();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                tail
+            }
+            (b'\\', _) => return None,
+            (byte, tail) => {
+                bytes.push(byte);
+                tail
+            }
+        };
+    }
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn test_escape_path() {
+        for path in [
+            OsStr::new("puppy"),
+            OsStr::new("puppy doggy/kitty"),
+            OsStr::new("back\\slash\nnewline\ttab"),
+            OsStr::from_bytes(b"not \xff utf-8"),
+        ] {
+            let escaped = escape_path(Path::new(path));
+            assert!(!escaped.contains('\n'));
+            assert_eq!(unescape_path(&escaped).unwrap(), Path::new(path));
+        }
+
+        assert_eq!(
+            escape_path(Path::new("back\\slash\nnewline")),
+            "back\\\\slash\\x0anewline"
+        );
+        assert_eq!(unescape_path("puppy\\"), None);
+        assert_eq!(unescape_path("puppy\\xzz"), None);
+        assert_eq!(unescape_path("puppy\\x+f"), None);
+    }
+}
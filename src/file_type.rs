@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+/// The type of a path in a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum FileType {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir,
+    /// A symbolic link.
+    Symlink,
+    /// Anything else, like a FIFO or a device file.
+    Other,
+}
+
+impl FileType {
+    pub(crate) fn from_std(file_type: std::fs::FileType) -> Self {
+        if file_type.is_file() {
+            Self::File
+        } else if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else {
+            Self::Other
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            FileType::File => "file",
+            FileType::Dir => "dir",
+            FileType::Symlink => "symlink",
+            FileType::Other => "other",
+        }
+    }
+
+    pub(crate) fn from_str(file_type: &str) -> Option<Self> {
+        match file_type {
+            "file" => Some(FileType::File),
+            "dir" => Some(FileType::Dir),
+            "symlink" => Some(FileType::Symlink),
+            "other" => Some(FileType::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Formatted as `file`, `dir`, `symlink`, or `other`.
+impl Display for FileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
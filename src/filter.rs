@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use globset::Glob;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+
+use crate::DiffOpts;
+use crate::GlobError;
+use crate::Result;
+
+/// Compiled include and exclude patterns from [`DiffOpts::include`] and [`DiffOpts::exclude`],
+/// and the names of ignore files to respect.
+#[derive(Debug, Clone)]
+pub(crate) struct Filter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore_filenames: Vec<String>,
+}
+
+impl Filter {
+    pub(crate) fn new(opts: &DiffOpts) -> Result<Self> {
+        Ok(Self {
+            include: if opts.include.is_empty() {
+                None
+            } else {
+                Some(build(&opts.include)?)
+            },
+            exclude: build(&opts.exclude)?,
+            ignore_filenames: opts
+                .ignore_files
+                .then(|| [".gitignore".to_owned(), ".ignore".to_owned()])
+                .into_iter()
+                .flatten()
+                .chain(opts.custom_ignore_filenames.iter().cloned())
+                .collect(),
+        })
+    }
+
+    /// The names of ignore files to respect.
+    pub(crate) fn ignore_filenames(&self) -> &[String] {
+        &self.ignore_filenames
+    }
+
+    /// Should the given path be walked?
+    ///
+    /// Directories are walked unless they're excluded, because they may contain included files.
+    pub(crate) fn is_match(&self, relative: &Path, is_dir: bool) -> bool {
+        if matches(&self.exclude, relative) {
+            return false;
+        }
+
+        is_dir || self.is_included(relative)
+    }
+
+    /// Does the given path or one of its parents match [`DiffOpts::include`]?
+    ///
+    /// Directories which don't are only reported if they contain paths which do.
+    pub(crate) fn is_included(&self, relative: &Path) -> bool {
+        match &self.include {
+            Some(include) => relative
+                .ancestors()
+                .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+                .any(|ancestor| matches(include, ancestor)),
+            None => true,
+        }
+    }
+}
+
+fn build(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|inner| GlobError {
+            pattern: pattern.clone(),
+            inner,
+        })?);
+    }
+    Ok(builder.build().map_err(|inner| GlobError {
+        pattern: patterns.join(", "),
+        inner,
+    })?)
+}
+
+/// Patterns match either the entire relative path or the file name.
+fn matches(globs: &GlobSet, relative: &Path) -> bool {
+    globs.is_match(relative)
+        || relative
+            .file_name()
+            .is_some_and(|file_name| globs.is_match(file_name))
+}
@@ -0,0 +1,300 @@
+use std::ffi::OsStr;
+use std::io::Cursor;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use git2::ObjectType;
+use git2::Odb;
+use git2::Oid;
+use git2::Repository;
+use git2::TreeWalkMode;
+use git2::TreeWalkResult;
+
+use crate::FileType;
+use crate::GitError;
+use crate::ReadError;
+use crate::Result;
+use crate::archive_index::ArchiveEntry;
+use crate::archive_index::ArchiveIndex;
+use crate::stat::Checksum;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// The mode git records for symbolic links.
+const SYMLINK_MODE: i32 = 0o120000;
+
+/// A tree read from a git repository's object database.
+///
+/// The tree is listed when it's opened, and blobs are read when they're needed.
+#[derive(Debug)]
+pub(crate) struct GitTree {
+    index: ArchiveIndex,
+    /// The object IDs of the files in the tree, indexed by [`ArchiveEntry::index`].
+    blobs: Vec<Oid>,
+    repo: Mutex<RepoHandle>,
+}
+
+/// [`Repository`] doesn't implement [`std::fmt::Debug`].
+struct RepoHandle(Repository);
+
+impl std::fmt::Debug for RepoHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Repository").field(&self.0.path()).finish()
+    }
+}
+
+impl GitTree {
+    pub(crate) fn open(repo_path: &Path, rev: &str) -> Result<Self> {
+        let error = |inner: git2::Error| GitError {
+            path: repo_path.to_owned(),
+            inner: inner.into(),
+        };
+
+        let repo = Repository::open(repo_path).map_err(error)?;
+        let tree = repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_tree())
+            .map_err(error)?;
+        let odb = repo.odb().map_err(error)?;
+
+        let root = PathBuf::from(format!("{}@{rev}", repo_path.display()));
+        let mut index = ArchiveIndex::new(&root);
+        let mut blobs = Vec::new();
+        let mut result = Ok(());
+        tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+            let relative = Path::new(OsStr::from_bytes(parent.as_bytes()))
+                .join(OsStr::from_bytes(entry.name_bytes()));
+            match archive_entry(&repo, &odb, entry, &mut blobs) {
+                Ok(entry) => {
+                    index.insert(relative, entry);
+                    TreeWalkResult::Ok
+                }
+                Err(err) => {
+                    result = Err(err);
+                    TreeWalkResult::Abort
+                }
+            }
+        })
+        .and(result)
+        .map_err(error)?;
+        drop(odb);
+        drop(tree);
+
+        Ok(Self {
+            index,
+            blobs,
+            repo: Mutex::new(RepoHandle(repo)),
+        })
+    }
+
+    /// Read the contents of the file at `relative`.
+    fn read_blob(&self, relative: &Path) -> Result<Vec<u8>> {
+        let oid = self.blobs[self.index.member(relative)?];
+        let repo = self
+            .repo
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let blob = repo.0.find_blob(oid).map_err(|err| ReadError {
+            path: self.index.path().join(relative),
+            inner: std::io::Error::other(err),
+        })?;
+        Ok(blob.content().to_vec())
+    }
+}
+
+fn archive_entry(
+    repo: &Repository,
+    odb: &Odb<'_>,
+    entry: &git2::TreeEntry<'_>,
+    blobs: &mut Vec<Oid>,
+) -> std::result::Result<ArchiveEntry, git2::Error> {
+    Ok(match entry.kind() {
+        Some(ObjectType::Tree) => ArchiveEntry::new(Stat::new(FileType::Dir, 0)),
+        Some(ObjectType::Blob) if entry.filemode() == SYMLINK_MODE => {
+            // Symbolic links store their targets as their contents.
+            let blob = repo.find_blob(entry.id())?;
+            ArchiveEntry {
+                target: Some(PathBuf::from(OsStr::from_bytes(blob.content()))),
+                ..ArchiveEntry::new(Stat::new(FileType::Symlink, blob.size() as u64))
+            }
+        }
+        Some(ObjectType::Blob) => {
+            let (size, _) = odb.read_header(entry.id())?;
+            blobs.push(entry.id());
+            let stat = Stat {
+                mode: Some(entry.filemode() as u32 & 0o7777),
+                checksum: Some(Checksum::GitOid(entry.id().as_bytes().to_vec())),
+                ..Stat::new(FileType::File, size as u64)
+            };
+            ArchiveEntry {
+                index: Some(blobs.len() - 1),
+                ..ArchiveEntry::new(stat)
+            }
+        }
+        // Submodules are recorded as commits.
+        _ => ArchiveEntry::new(Stat::new(FileType::Other, 0)),
+    })
+}
+
+impl Tree for GitTree {
+    fn root(&self) -> &Path {
+        self.index.path()
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        Ok(self.index.read_dir(relative))
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        self.index.stat(relative)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        self.index.read_link(relative)
+    }
+
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        tracing::trace!("Reading {relative:?} from {:?}", self.index.path());
+        Ok(Some(Box::new(Cursor::new(self.read_blob(relative)?))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::CompareMetadata;
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::DiffTag;
+    use crate::Error;
+    use crate::Source;
+
+    /// Commit everything in the repository's working directory.
+    fn commit(repo: &git2::Repository) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Puppy", "puppy@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Commit",
+            &tree,
+            parent.as_slice().iter().collect::<Vec<_>>().as_slice(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_git() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .mode("a/puppy", 0o644)
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap()
+            .mode("doggy", 0o644)
+            .unwrap()
+            .file("run", "run\n")
+            .unwrap()
+            .mode("run", 0o755)
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap();
+        let path = AsRef::<Path>::as_ref(&tree).to_owned();
+        let repo = git2::Repository::init(&path).unwrap();
+        commit(&repo);
+
+        tree.file("doggy", "kitty\n")
+            .unwrap()
+            .file("new", "new\n")
+            .unwrap();
+        commit(&repo);
+
+        let opts = DiffOpts::new()
+            .follow_links(false)
+            .exclude(".git")
+            .compare_metadata(CompareMetadata::new().mode(true));
+        let diff = Diff::with_sources(
+            Source::git(&path, "HEAD~")?,
+            Source::git(&path, "HEAD")?,
+            opts.clone(),
+        )?;
+        assert_eq!(diff.to_string(), "~ doggy\n+ new\n");
+        // Blobs are compared by their object IDs, so they don't need to be hashed.
+        let puppy = diff.get(Path::new("a/puppy")).unwrap();
+        assert_eq!(puppy.tag(), DiffTag::Equal);
+        assert_eq!(puppy.deleted().unwrap().hash(), None);
+        assert_eq!(
+            puppy.deleted().unwrap().base(),
+            Path::new(&format!("{}@HEAD~", path.display()))
+        );
+
+        tree.file("run", "walk\n").unwrap();
+        let diff = Diff::with_sources(
+            Source::git(&path, "HEAD")?,
+            Source::dir(&path),
+            opts.clone(),
+        )?;
+        assert_eq!(diff.to_string(), "~ run\n");
+
+        assert!(matches!(Source::git(&path, "puppy"), Err(Error::Git(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_revs() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap();
+        let path = AsRef::<Path>::as_ref(&tree).to_owned();
+        let repo = Repository::init(&path).unwrap();
+        commit(&repo);
+
+        // Revisions can name subtrees.
+        let subtree = path.join("a");
+        let diff = Diff::with_sources(
+            Source::git(&path, "HEAD:a")?,
+            Source::dir(&subtree),
+            DiffOpts::new(),
+        )?;
+        assert_eq!(diff.to_string(), "");
+        assert_eq!(diff.len(), 1);
+
+        for rev in ["HEAD~", "HEAD:nowhere", "HEAD:doggy", "not a rev"] {
+            assert!(
+                matches!(Source::git(&path, rev), Err(Error::Git(_))),
+                "{rev}"
+            );
+        }
+        assert!(matches!(Source::git(&subtree, "HEAD"), Err(Error::Git(_))));
+
+        // Missing objects are errors too.
+        let oid = Oid::hash_object(ObjectType::Blob, b"doggy\n")
+            .unwrap()
+            .to_string();
+        std::fs::remove_file(path.join(".git/objects").join(&oid[..2]).join(&oid[2..])).unwrap();
+        assert!(matches!(Source::git(&path, "HEAD"), Err(Error::Git(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::Metadata;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::HashCacheError;
+use crate::Result;
+use crate::escape::escape_path;
+use crate::escape::unescape_path;
+use crate::hash_file::hash_file;
+
+/// The first line of a cache file. Files with any other first line are ignored.
+const HEADER: &str = "diff-trees hash cache v1";
+
+/// Files modified more recently than this aren't cached, because they may be modified again
+/// without their modification time changing.
+const RACY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A persistent cache of [`blake3`] hashes, so that unchanged files aren't rehashed every time
+/// the same trees are diffed.
+///
+/// Hashes are keyed by absolute path, and a cached hash is only used if the file's device, inode,
+/// size, and modification time all match the cached values. Otherwise, the file is rehashed and
+/// the cache is updated. Like `make` and `rsync`'s quick check, this means a file which is
+/// modified without changing its size or modification time (e.g. with `touch -r`) is not
+/// rehashed. Files modified in the last few seconds are never cached, so that quick successive
+/// writes aren't missed.
+///
+/// When the cache is saved, hashes which weren't used since it was loaded are dropped if their
+/// files have since changed or been deleted, so the cache doesn't grow without bound. To
+/// invalidate the cache entirely, delete the cache file. Cache files which are corrupt or were
+/// written by an incompatible version are ignored.
+///
+/// Use [`crate::DiffOpts::hash_cache`] to diff with a cache, and [`HashCache::save`] to write it
+/// back to disk afterwards. Clones of a [`HashCache`] share the same entries.
+#[derive(Clone)]
+pub struct HashCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, CachedHash>>,
+    /// Whether the entries have changed since they were loaded.
+    dirty: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Key {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+impl Key {
+    fn new(metadata: &Metadata) -> Self {
+        Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedHash {
+    key: Key,
+    hash: blake3::Hash,
+    /// Whether the hash was looked up or inserted since the cache was loaded.
+    used: bool,
+}
+
+impl HashCache {
+    /// Load a cache from the given file.
+    ///
+    /// If the file doesn't exist, the cache starts out empty and the file is created when the
+    /// cache is saved.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = match std::fs::File::open(&path) {
+            Ok(file) => {
+                let entries = parse(BufReader::new(file)).map_err(|inner| HashCacheError {
+                    path: path.clone(),
+                    inner,
+                })?;
+                entries.unwrap_or_else(|| {
+                    tracing::warn!(?path, "Ignoring invalid hash cache");
+                    Default::default()
+                })
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(inner) => return Err(HashCacheError { path, inner }.into()),
+        };
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                path,
+                entries: Mutex::new(entries),
+                dirty: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    /// The file this cache is loaded from and saved to.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// The number of cached hashes.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the cache to its file, if it's changed since it was loaded.
+    ///
+    /// Unused hashes of files which have changed or been deleted are dropped first. The file is
+    /// replaced atomically, so a cache file is never partially written.
+    pub fn save(&self) -> Result<()> {
+        let evicted = self.evict();
+        if !self.inner.dirty.swap(false, Ordering::SeqCst) && !evicted {
+            return Ok(());
+        }
+
+        let path = &self.inner.path;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        let write = || -> std::io::Result<()> {
+            let mut lines = self
+                .entries()
+                .iter()
+                .map(|(path, cached)| {
+                    let key = cached.key;
+                    format!(
+                        "{} {} {} {} {} {} {}\n",
+                        cached.hash.to_hex(),
+                        key.dev,
+                        key.ino,
+                        key.size,
+                        key.mtime,
+                        key.mtime_nsec,
+                        escape_path(path)
+                    )
+                })
+                .collect::<Vec<_>>();
+            lines.sort();
+
+            let mut writer = BufWriter::new(std::fs::File::create(&temp)?);
+            writeln!(writer, "{HEADER}")?;
+            for line in lines {
+                writer.write_all(line.as_bytes())?;
+            }
+            writer.into_inner()?.sync_all()?;
+            std::fs::rename(&temp, path)
+        };
+
+        write().map_err(|inner| {
+            self.inner.dirty.store(true, Ordering::SeqCst);
+            HashCacheError {
+                path: path.clone(),
+                inner,
+            }
+            .into()
+        })
+    }
+
+    /// Drop the unused hashes of files which have changed or been deleted.
+    ///
+    /// Returns whether any hashes were dropped.
+    fn evict(&self) -> bool {
+        let mut entries = self.entries();
+        let len = entries.len();
+        entries.retain(|path, cached| {
+            cached.used
+                || std::fs::metadata(path).is_ok_and(|metadata| Key::new(&metadata) == cached.key)
+        });
+        entries.len() != len
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, CachedHash>> {
+        // The entries are always consistent, even if another thread panicked.
+        self.inner
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Hash the file at `path`, or get its hash from the cache if it hasn't changed.
+    pub(crate) fn hash_file(&self, path: &Path, metadata: &Metadata) -> Result<blake3::Hash> {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+        let key = Key::new(metadata);
+
+        if let Some(cached) = self.entries().get_mut(&absolute)
+            && cached.key == key
+        {
+            tracing::trace!(?path, "Using cached hash");
+            cached.used = true;
+            return Ok(cached.hash);
+        }
+
+        let hash = hash_file(path)?;
+
+        let racy = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_none_or(|age| age < RACY_INTERVAL);
+        if !racy {
+            self.entries().insert(
+                absolute,
+                CachedHash {
+                    key,
+                    hash,
+                    used: true,
+                },
+            );
+            self.inner.dirty.store(true, Ordering::SeqCst);
+        }
+
+        Ok(hash)
+    }
+}
+
+impl Debug for HashCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HashCache")
+            .field("path", &self.inner.path)
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parse a cache file.
+///
+/// Returns `None` if the file isn't a valid cache file.
+fn parse(reader: impl BufRead) -> std::io::Result<Option<HashMap<PathBuf, CachedHash>>> {
+    let mut lines = reader.lines();
+    match lines.next().transpose()? {
+        Some(header) if header == HEADER => {}
+        _ => return Ok(None),
+    }
+
+    let mut entries = HashMap::new();
+    for line in lines {
+        match parse_line(&line?) {
+            Some((path, cached)) => {
+                entries.insert(path, cached);
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(entries))
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, CachedHash)> {
+    let mut fields = line.splitn(7, ' ');
+    let hash = blake3::Hash::from_hex(fields.next()?).ok()?;
+    let key = Key {
+        dev: fields.next()?.parse().ok()?,
+        ino: fields.next()?.parse().ok()?,
+        size: fields.next()?.parse().ok()?,
+        mtime: fields.next()?.parse().ok()?,
+        mtime_nsec: fields.next()?.parse().ok()?,
+    };
+    let path = unescape_path(fields.next()?)?;
+    Some((
+        path,
+        CachedHash {
+            key,
+            hash,
+            used: false,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::Error;
+
+    #[test]
+    fn test_hash_cache() -> Result<()> {
+        let past = SystemTime::now() - Duration::from_secs(600);
+
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "kitty")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap();
+
+        let cache_dir = TempTree::new().unwrap();
+        let cache_path = cache_dir.child("cache");
+
+        fn diff_with(old: &Path, new: &Path, cache: &HashCache) -> Result<String> {
+            Ok(
+                Diff::with_opts(old, new, DiffOpts::default().hash_cache(cache.clone()))?
+                    .to_string(),
+            )
+        }
+
+        let cache = HashCache::open(&cache_path)?;
+        assert!(cache.is_empty());
+        assert_eq!(diff_with(old.as_ref(), new.as_ref(), &cache)?, "~ puppy\n");
+        assert_eq!(cache.len(), 2);
+        cache.save()?;
+
+        // If a file changes without its size or mtime changing, the cached hash is used.
+        old.file("puppy", "kitty")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap();
+        let cache = HashCache::open(&cache_path)?;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(diff_with(old.as_ref(), new.as_ref(), &cache)?, "~ puppy\n");
+        assert_eq!(Diff::new(old.as_ref(), new.as_ref())?.to_string(), "");
+
+        // Changing the mtime invalidates the cached hash.
+        old.mtime("puppy", past + Duration::from_secs(1)).unwrap();
+        assert_eq!(diff_with(old.as_ref(), new.as_ref(), &cache)?, "");
+        assert_eq!(cache.len(), 2);
+
+        // Recently modified files aren't cached.
+        let cache = HashCache::open(cache_dir.child("recent"))?;
+        old.mtime("puppy", SystemTime::now()).unwrap();
+        assert_eq!(diff_with(old.as_ref(), new.as_ref(), &cache)?, "");
+        assert_eq!(cache.len(), 1);
+
+        // Invalid cache files are ignored.
+        std::fs::write(&cache_path, "puppy").unwrap();
+        assert!(HashCache::open(&cache_path)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_cache_invalid() -> Result<()> {
+        let cache_dir = TempTree::new().unwrap();
+        let cache_path = cache_dir.child("cache");
+        let hash = blake3::hash(b"puppy").to_hex();
+
+        let valid = format!("{HEADER}\n{hash} 1 2 5 1000 0 /puppy\n");
+        std::fs::write(&cache_path, &valid).unwrap();
+        assert_eq!(HashCache::open(&cache_path)?.len(), 1);
+
+        for contents in [
+            String::new(),
+            valid.replace(HEADER, "diff-trees hash cache v2"),
+            format!("{valid}{hash} 1 2 5 1000\n"),
+            valid.replace(hash.as_str(), "puppy"),
+            valid.replace(" 1000 ", " yesterday "),
+            valid.replace("/puppy", "/pup\\xzzpy"),
+        ] {
+            std::fs::write(&cache_path, &contents).unwrap();
+            assert!(HashCache::open(&cache_path)?.is_empty(), "{contents:?}");
+        }
+
+        // Invalid caches are replaced when they're saved.
+        let mut tree = TempTree::new().unwrap();
+        tree.file("puppy", "puppy")
+            .unwrap()
+            .mtime("puppy", SystemTime::now() - Duration::from_secs(600))
+            .unwrap();
+        let path = tree.child("puppy");
+        let metadata = std::fs::metadata(&path).unwrap();
+        let cache = HashCache::open(&cache_path)?;
+        cache.hash_file(&path, &metadata)?;
+        cache.save()?;
+        assert_eq!(HashCache::open(&cache_path)?.len(), 1);
+
+        // Errors reading the cache file aren't ignored.
+        assert!(matches!(
+            HashCache::open(AsRef::<Path>::as_ref(&cache_dir)),
+            Err(Error::HashCache(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_cache_save() -> Result<()> {
+        let cache_dir = TempTree::new().unwrap();
+
+        // Unchanged caches aren't written.
+        let cache = HashCache::open(cache_dir.child("cache"))?;
+        cache.save()?;
+        assert!(!cache.path().exists());
+
+        let mut tree = TempTree::new().unwrap();
+        tree.file("pup py\\", "puppy")
+            .unwrap()
+            .mtime("pup py\\", SystemTime::now() - Duration::from_secs(600))
+            .unwrap();
+
+        let cache = HashCache::open(cache_dir.child("missing/cache"))?;
+        let path = tree.child("pup py\\");
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(cache.hash_file(&path, &metadata)?, blake3::hash(b"puppy"));
+        assert!(matches!(cache.save(), Err(Error::HashCache(_))));
+
+        // Failed saves are retried, and paths are escaped.
+        std::fs::create_dir(cache_dir.child("missing")).unwrap();
+        cache.save()?;
+        let cache = HashCache::open(cache.path())?;
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.hash_file(&path, &metadata)?, blake3::hash(b"puppy"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_cache_evict() -> Result<()> {
+        let past = SystemTime::now() - Duration::from_secs(600);
+        let mut tree = TempTree::new().unwrap();
+        tree.file("puppy", "puppy")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap()
+            .file("doggy", "doggy")
+            .unwrap()
+            .mtime("doggy", past)
+            .unwrap()
+            .file("kitty", "kitty")
+            .unwrap()
+            .mtime("kitty", past)
+            .unwrap();
+
+        let cache_dir = TempTree::new().unwrap();
+        let cache = HashCache::open(cache_dir.child("cache"))?;
+        for name in ["puppy", "doggy", "kitty"] {
+            let path = tree.child(name);
+            cache.hash_file(&path, &std::fs::metadata(&path).unwrap())?;
+        }
+        cache.save()?;
+
+        // Unused hashes of deleted or changed files are dropped, but other hashes are kept.
+        std::fs::remove_file(tree.child("doggy")).unwrap();
+        tree.file("kitty", "kitten").unwrap();
+        let cache = HashCache::open(cache.path())?;
+        assert_eq!(cache.len(), 3);
+        cache.save()?;
+        assert_eq!(cache.len(), 1);
+        let cache = HashCache::open(cache.path())?;
+        assert_eq!(cache.len(), 1);
+        let path = tree.child("puppy");
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(cache.hash_file(&path, &metadata)?, blake3::hash(b"puppy"));
+
+        Ok(())
+    }
+}
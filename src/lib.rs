@@ -1,6 +1,21 @@
 //! Diff two directory trees based on their contents and format the resulting diff.
 //!
-//! Construct a diff with [`Diff::new`], which can be formatted or inspected.
+//! Construct a diff with [`Diff::new`], which can be formatted or inspected. Use
+//! [`Diff::with_opts`] to customize how the diff is computed, e.g. with a custom [`Comparator`].
+//! Use [`Diff::with_sources`] to diff a directory against a [`Manifest`] saved with
+//! [`Manifest::from_dir`] instead, or implement [`Tree`] to diff trees which aren't on the local
+//! filesystem (like a virtual filesystem) with [`Source::tree`]. [`MemoryTree`] builds a tree in
+//! memory, which is handy for describing expected trees in tests. Use [`ThreeWayDiff`] to see how
+//! two trees diverged from a common base.
+//!
+//! With the `serde` feature enabled, [`Diff`] implements `serde::Serialize` for machine-readable
+//! output (e.g. JSON). With the `json` feature enabled, `Diff::write_ndjson` streams entries as
+//! newline-delimited JSON without collecting the whole diff in memory; see [`Diff::stream`].
+//! With the `tar` feature enabled, `Source::tar` reads tar archives (optionally compressed with
+//! gzip or zstd) directly, so they can be diffed without extracting them. With the `zip` feature
+//! enabled, `Source::zip` does the same for zip archives (including `.jar` and `.whl` files).
+//! With the `git` feature enabled, `Source::git` reads trees (like commits) from a local git
+//! repository.
 
 #![deny(missing_docs)]
 
@@ -9,38 +24,106 @@ use std::ops::Deref;
 use std::path::Path;
 
 use iddqd::IdOrdMap;
-use walkdir::WalkDir;
 
-mod candidate_is_same;
+mod archive_index;
+mod base;
+mod candidate_changes;
+mod changes;
+mod comparator;
+mod compare_metadata;
+mod content_diff;
+mod deferred;
+mod detect_renames;
 mod diff_entry;
+mod diff_opts;
 mod diff_tag;
+mod dir_stack;
 mod display_diff;
 mod display_diff_opts;
+mod display_three_way_diff;
 mod error;
+mod error_sink;
+mod escape;
+mod file_type;
+mod filter;
+#[cfg(feature = "git")]
+mod git_tree;
+mod hash_cache;
 mod hash_file;
+mod local_tree;
+mod manifest;
+mod memory_tree;
 mod path_info;
+mod read_link;
+#[cfg(feature = "serde")]
+mod serialize;
+mod source;
+mod stat;
 mod strip_prefix;
+#[cfg(feature = "tar")]
+mod tar_tree;
+mod three_way_diff;
+mod three_way_entry;
+mod three_way_tag;
+mod tree;
+mod walk;
+#[cfg(feature = "zip")]
+mod zip_tree;
 
+pub use changes::Changes;
+pub use comparator::Blake3Comparator;
+pub use comparator::Comparator;
+pub use compare_metadata::CompareMetadata;
+pub use content_diff::ContentDiff;
 pub use diff_entry::DiffEntry;
+pub use diff_opts::DiffOpts;
 pub use diff_tag::DiffTag;
 pub use display_diff_opts::DisplayDiffOpts;
+pub use error::ArchiveError;
+pub use error::CompareError;
 pub use error::Error;
+pub use error::GitError;
+pub use error::GlobError;
+pub use error::HashCacheError;
 pub use error::HashError;
+pub use error::LoopError;
+pub use error::ManifestError;
 pub use error::MetadataError;
+pub use error::ReadError;
+pub use error::ReadLinkError;
 pub use error::Result;
+pub use error::SaveManifestError;
 pub use error::StripPrefixError;
 pub use error::TraverseError;
+pub use error::TreeError;
 pub use error::WalkDirMetadataError;
+pub use error::WriteError;
+pub use file_type::FileType;
+pub use hash_cache::HashCache;
+pub use manifest::Manifest;
+pub use manifest::ManifestEntry;
+pub use memory_tree::MemoryTree;
+pub use path_info::PathInfo;
+pub use source::Source;
+pub use stat::Stat;
+pub use three_way_diff::ThreeWayDiff;
+pub use three_way_entry::ThreeWayEntry;
+pub use three_way_tag::ThreeWayTag;
+pub use tree::Tree;
+pub use tree::TreeEntry;
 
-use candidate_is_same::candidate_is_same;
+use base::Base;
+use detect_renames::detect_renames;
 use display_diff::DisplayDiff;
-use path_info::PathInfo;
-use strip_prefix::strip_prefix;
+use walk::Walk;
 
 /// A diff of trees in terms of relative paths.
 #[derive(Debug)]
 pub struct Diff<'a> {
+    old: Base<'a>,
+    new: Base<'a>,
     entries: IdOrdMap<DiffEntry<'a>>,
+    errors: Vec<Error>,
 }
 
 impl<'a> Deref for Diff<'a> {
@@ -75,168 +158,162 @@ impl<'a> Diff<'a> {
     /// Diff two directory trees.
     ///
     /// Paths are compared for equality with [`blake3`], which has good performance characteristics
-    /// in my testing. In the future, I hope to [add pluggable comparators][issue-2], [rename
-    /// detection][issue-9], and the [ability to produce a text diff of the compared
-    /// files][issue-3].
-    ///
-    /// Note that directory entries which appear in both trees are considered to be
-    /// [`DiffTag::Replace`]d, but this does not account for whether or not their contents have
-    /// changed. A future version of this library may do something more intuitive in this case.
+    /// in my testing. Metadata is not compared unless enabled with
+    /// [`DiffOpts::compare_metadata`]. Use [`Diff::with_opts`] and [`DiffOpts::comparator`] to
+    /// compare files differently, [`DiffOpts::detect_renames`] to detect renamed paths, or
+    /// [`DiffOpts::text_diff`] to produce a text diff of changed files. Use [`DiffOpts::include`]
+    /// and [`DiffOpts::exclude`] to restrict which paths are diffed, and
+    /// [`DiffOpts::ignore_files`] to respect `.gitignore` files. Use [`DiffOpts::tolerate_errors`]
+    /// to produce a diff even if some paths can't be read.
     ///
-    /// [issue-2]: https://github.com/9999years/diff-trees/issues/2
-    /// [issue-9]: https://github.com/9999years/diff-trees/issues/9
-    /// [issue-3]: https://github.com/9999years/diff-trees/issues/3
+    /// Directory entries which appear in both trees are [`DiffTag::Equal`] if none of their
+    /// contents have changed, and [`DiffTag::Replace`]d otherwise. Paths which change type (e.g.
+    /// from a file to a directory) are [`DiffTag::TypeChange`]d.
     pub fn new(old: &'a Path, new: &'a Path) -> Result<Self> {
-        let mut diff = Self {
-            entries: IdOrdMap::new(),
-        };
-
-        diff.walk_removed_tree(old, new)?;
-        diff.walk_added_tree(new)?;
-
-        Ok(diff)
+        Self::with_opts(old, new, Default::default())
     }
 
-    fn walk_removed_tree(&mut self, old: &'a Path, new: &'a Path) -> Result<()> {
-        let walker = WalkDir::new(old).follow_links(true);
-        let mut iterator = walker.into_iter();
-
-        loop {
-            let removed_entry = match iterator.next() {
-                Some(entry) => entry.map_err(|inner| {
-                    Error::Traverse(TraverseError {
-                        path: old.to_path_buf(),
-                        inner,
-                    })
-                }),
-                None => break,
-            }?;
-
-            if removed_entry.depth() == 0 {
-                continue;
-            }
-
-            let relative = strip_prefix(removed_entry.path(), old)?.to_path_buf();
-
-            let removed_metadata =
-                removed_entry
-                    .metadata()
-                    .map_err(|inner| WalkDirMetadataError {
-                        path: removed_entry.path().to_owned(),
-                        inner,
-                    })?;
-
-            let mut entry = DiffEntry {
-                relative,
-                tag: DiffTag::Delete,
-                deleted: None,
-                inserted: None,
-            };
-
-            let candidate = new.join(&entry.relative);
-            let candidate_metadata = match candidate.metadata() {
-                Ok(metadata) => Some(metadata),
-                Err(err) => {
-                    if err.kind() == std::io::ErrorKind::NotFound {
-                        None
-                    } else {
-                        return Err(MetadataError {
-                            path: candidate.clone(),
-                            inner: err,
-                        }
-                        .into());
-                    }
-                }
-            };
-
-            entry.tag = match candidate_metadata.as_ref() {
-                Some(candidate_metadata) => candidate_is_same(
-                    removed_entry.path(),
-                    &removed_metadata,
-                    &candidate,
-                    candidate_metadata,
-                )?,
-                None => DiffTag::Delete,
-            };
-
-            entry.inserted = candidate_metadata.map(|metadata| PathInfo {
-                metadata,
-                base: new,
-            });
-
-            if removed_entry.file_type().is_dir()
-                && let DiffTag::Delete = entry.tag
-            {
-                // Don't recurse if a directory has been removed.
-                iterator.skip_current_dir();
-            }
+    /// Diff two directory trees with the given options.
+    pub fn with_opts(old: &'a Path, new: &'a Path, opts: DiffOpts) -> Result<Self> {
+        Self::with_sources(old.into(), new.into(), opts)
+    }
 
-            entry.deleted = Some(PathInfo {
-                metadata: removed_metadata,
-                base: old,
-            });
+    /// Diff two [`Source`]s with the given options, e.g. to diff a directory against a saved
+    /// [`Manifest`].
+    pub fn with_sources(old: Source<'a>, new: Source<'a>, opts: DiffOpts) -> Result<Self> {
+        let mut walk = Walk::new(&old, &new, &opts)?;
+        let mut entries = IdOrdMap::new();
 
-            if let Some(overwritten) = self.entries.insert_overwrite(entry) {
+        walk.run(&mut |entry| {
+            if let Some(overwritten) = entries.insert_overwrite(entry) {
                 tracing::debug!(?overwritten, "Got two diff entries for a single path");
             }
+            Ok(())
+        })?;
+
+        if opts.detect_renames {
+            detect_renames(&mut entries, &mut walk)?;
         }
-        Ok(())
+
+        Ok(Self {
+            old: old.base(),
+            new: new.base(),
+            entries,
+            errors: walk.errors.errors,
+        })
     }
 
-    fn walk_added_tree(&mut self, new: &'a Path) -> Result<()> {
-        let walker = WalkDir::new(new).follow_links(true);
-        let mut iterator = walker.into_iter();
+    /// Diff two directory trees, passing each entry to `callback` as soon as it's determined
+    /// rather than collecting them into a [`Diff`].
+    ///
+    /// Entries within each directory are produced in order, but directories are produced after
+    /// their contents (because a directory's tag depends on its contents). This includes
+    /// directories which replaced or were replaced by another type of file. If
+    /// [`DiffOpts::detect_renames`] is enabled, deleted and inserted entries are held back and
+    /// produced at the end, once renames have been detected.
+    ///
+    /// Errors returned from `callback` stop the diff. Returns the errors which were tolerated
+    /// (see [`Diff::errors`]).
+    pub fn stream(
+        old: &'a Path,
+        new: &'a Path,
+        opts: DiffOpts,
+        callback: impl FnMut(DiffEntry<'a>) -> Result<()>,
+    ) -> Result<Vec<Error>> {
+        Self::stream_sources(old.into(), new.into(), opts, callback)
+    }
 
-        loop {
-            let added_entry = match iterator.next() {
-                Some(entry) => entry.map_err(|inner| {
-                    Error::Traverse(TraverseError {
-                        path: new.to_path_buf(),
-                        inner,
-                    })
-                }),
-                None => break,
-            }?;
+    /// Diff two [`Source`]s, passing each entry to `callback` as soon as it's determined. See
+    /// [`Diff::stream`].
+    pub fn stream_sources(
+        old: Source<'a>,
+        new: Source<'a>,
+        opts: DiffOpts,
+        mut callback: impl FnMut(DiffEntry<'a>) -> Result<()>,
+    ) -> Result<Vec<Error>> {
+        let mut walk = Walk::new(&old, &new, &opts)?;
 
-            if added_entry.depth() == 0 {
-                continue;
+        if opts.detect_renames {
+            let mut candidates = IdOrdMap::new();
+            walk.run(&mut |entry| match entry.tag {
+                DiffTag::Delete | DiffTag::Insert => {
+                    candidates.insert_overwrite(entry);
+                    Ok(())
+                }
+                _ => callback(entry),
+            })?;
+            detect_renames(&mut candidates, &mut walk)?;
+            for entry in candidates {
+                callback(entry)?;
             }
+        } else {
+            walk.run(&mut callback)?;
+        }
 
-            let relative = strip_prefix(added_entry.path(), new)?.to_path_buf();
+        Ok(walk.errors.errors)
+    }
 
-            match self.entries.get(relative.as_path()) {
-                Some(diff_entry) => {
-                    if let DiffTag::Delete = diff_entry.tag {
-                        // Don't recurse if a directory has been removed.
-                        iterator.skip_current_dir();
-                        continue;
-                    }
-                }
-                None => {
-                    if added_entry.file_type().is_dir() {
-                        iterator.skip_current_dir();
-                    }
+    /// Diff two directory trees, writing each entry to `writer` as a line of JSON as soon as it's
+    /// determined (newline-delimited JSON).
+    ///
+    /// Entries are written in the order described in [`Diff::stream`], and serialized like the
+    /// entries of a serialized [`Diff`]. `writer` is not buffered, so you may want to wrap it in a
+    /// [`std::io::BufWriter`].
+    #[cfg(feature = "json")]
+    pub fn write_ndjson(
+        old: &'a Path,
+        new: &'a Path,
+        opts: DiffOpts,
+        writer: impl std::io::Write,
+    ) -> Result<Vec<Error>> {
+        Self::write_ndjson_sources(old.into(), new.into(), opts, writer)
+    }
 
-                    if let Some(overwritten) = self.entries.insert_overwrite(DiffEntry {
-                        relative,
-                        tag: DiffTag::Insert,
-                        deleted: None,
-                        inserted: Some(PathInfo {
-                            metadata: added_entry.metadata().map_err(|inner| {
-                                WalkDirMetadataError {
-                                    path: added_entry.path().to_owned(),
-                                    inner,
-                                }
-                            })?,
-                            base: new,
-                        }),
-                    }) {
-                        tracing::debug!(?overwritten, "Got two diff entries for a single path");
-                    };
-                }
-            }
+    /// Diff two [`Source`]s, writing each entry to `writer` as a line of JSON. See
+    /// [`Diff::write_ndjson`].
+    #[cfg(feature = "json")]
+    pub fn write_ndjson_sources(
+        old: Source<'a>,
+        new: Source<'a>,
+        opts: DiffOpts,
+        mut writer: impl std::io::Write,
+    ) -> Result<Vec<Error>> {
+        let errors = Self::stream_sources(old, new, opts, |entry| {
+            serde_json::to_writer(&mut writer, &entry)
+                .map_err(|err| WriteError { inner: err.into() })?;
+            writer
+                .write_all(b"\n")
+                .map_err(|inner| WriteError { inner })?;
+            Ok(())
+        })?;
+        writer.flush().map_err(|inner| WriteError { inner })?;
+        Ok(errors)
+    }
+
+    /// Convert this into a [`Diff`] which owns the paths being diffed, so that it can be returned
+    /// from functions or sent between threads.
+    ///
+    /// The paths are shared between entries, so this only allocates them once.
+    pub fn into_owned(self) -> Diff<'static> {
+        let old = self.old.into_owned();
+        let new = self.new.into_owned();
+        let mut entries = IdOrdMap::with_capacity(self.entries.len());
+        for entry in self.entries {
+            entries.insert_overwrite(entry.with_bases(&old, &new));
         }
-        Ok(())
+        Diff {
+            old,
+            new,
+            entries,
+            errors: self.errors,
+        }
+    }
+
+    /// Errors encountered while diffing individual paths.
+    ///
+    /// This is always empty unless [`DiffOpts::tolerate_errors`] is enabled.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
     }
 
     /// [`Display`] this diff with the given options.
@@ -257,6 +334,10 @@ impl<'a> Display for Diff<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
     use super::*;
     use testlib::TempTree;
 
@@ -303,25 +384,300 @@ mod tests {
     }
 
     #[test]
-    fn test_complex() -> Result<()> {
+    fn test_custom_comparator() -> Result<()> {
+        struct TrimComparator;
+
+        impl Comparator for TrimComparator {
+            fn is_same(
+                &self,
+                relative: &Path,
+                old: &PathInfo<'_>,
+                new: &PathInfo<'_>,
+            ) -> Result<bool> {
+                match (old.read(relative)?, new.read(relative)?) {
+                    (Some(old), Some(new)) => Ok(old.trim_ascii_end() == new.trim_ascii_end()),
+                    // Manifests don't record the contents of files.
+                    _ => Blake3Comparator.is_same(relative, old, new),
+                }
+            }
+        }
+
         let mut old = TempTree::new().unwrap();
-        old.dir("a")
+        old.file("puppy", "puppy").unwrap();
+        old.file("doggy", "doggy").unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "puppy  \n").unwrap();
+        new.file("doggy", "  doggy").unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().comparator(TrimComparator),
+        )?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("doggy"), DiffTag::Replace),
+                (Path::new("puppy"), DiffTag::Equal),
+            ]
+        );
+
+        // Files are read from the tree they're in, not from `base().join(relative)`.
+        let memory = MemoryTree::new("mem")
+            .file("puppy", "puppy")
+            .file("doggy", "doggy");
+        let diff = Diff::with_sources(
+            Source::tree(memory),
+            Source::dir(new.as_ref()),
+            DiffOpts::new().comparator(TrimComparator),
+        )?;
+        assert_eq!(diff.to_string(), "~ doggy\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_renames() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .file("empty", "")
+            .unwrap()
+            .dir("a")
             .unwrap()
             .file("a/1", "1")
             .unwrap()
             .file("a/2", "2")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("doggy", "puppy")
+            .unwrap()
+            .file("also-empty", "")
             .unwrap()
             .dir("b")
             .unwrap()
             .file("b/1", "1")
             .unwrap()
             .file("b/2", "2")
+            .unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().detect_renames(true),
+        )?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(|entry| (entry.renamed_from(), entry.relative(), entry.tag()))
+                .collect::<Vec<_>>(),
+            vec![
+                (None, Path::new("also-empty"), DiffTag::Insert),
+                (Some(Path::new("a")), Path::new("b"), DiffTag::Rename),
+                (
+                    Some(Path::new("puppy")),
+                    Path::new("doggy"),
+                    DiffTag::Rename
+                ),
+                (None, Path::new("empty"), DiffTag::Delete),
+            ]
+        );
+
+        assert_eq!(
+            diff.to_string(),
+            indoc::indoc!(
+                r#"
+                + also-empty
+                > a/ -> b/
+                > puppy -> doggy
+                - empty
+                "#
+            )
+        );
+
+        // Metadata changes of renamed paths are still reported.
+        drop(diff);
+        old.mode("puppy", 0o644).unwrap();
+        new.mode("doggy", 0o755).unwrap();
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new()
+                .detect_renames(true)
+                .compare_metadata(CompareMetadata::new().mode(true)),
+        )?;
+        let doggy = diff.get(Path::new("doggy")).unwrap();
+        assert_eq!(doggy.tag(), DiffTag::Rename);
+        assert!(doggy.changes().mode());
+        assert!(!doggy.changes().content());
+        assert!(diff.to_string().contains("> puppy -> doggy (mode)\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_tags() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a/b/c")
             .unwrap()
-            .dir("c")
+            .file("a/b/c/1", "1")
             .unwrap()
-            .file("c/1", "1")
+            .dir("x/y")
             .unwrap()
-            .file("c/2", "2")
+            .file("x/y/1", "1")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a/b/c")
+            .unwrap()
+            .file("a/b/c/1", "1")
+            .unwrap()
+            .file("a/b/c/2", "2")
+            .unwrap()
+            .dir("x/y")
+            .unwrap()
+            .file("x/y/1", "1")
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("a"), DiffTag::Replace),
+                (Path::new("a/b"), DiffTag::Replace),
+                (Path::new("a/b/c"), DiffTag::Replace),
+                (Path::new("a/b/c/1"), DiffTag::Equal),
+                (Path::new("a/b/c/2"), DiffTag::Insert),
+                (Path::new("x"), DiffTag::Equal),
+                (Path::new("x/y"), DiffTag::Equal),
+                (Path::new("x/y/1"), DiffTag::Equal),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_metadata() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("run", "#!/bin/sh")
+            .unwrap()
+            .mode("run", 0o644)
+            .unwrap()
+            .file("edit", "puppy")
+            .unwrap()
+            .mode("edit", 0o644)
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("run", "#!/bin/sh")
+            .unwrap()
+            .mode("run", 0o755)
+            .unwrap()
+            .file("edit", "doggy")
+            .unwrap()
+            .mode("edit", 0o755)
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+        assert_eq!(diff.to_string(), "~ edit\n");
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().compare_metadata(CompareMetadata::new().mode(true)),
+        )?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(|entry| (
+                    entry.relative(),
+                    entry.changes().content(),
+                    entry.changes().mode()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("edit"), true, true),
+                (Path::new("run"), false, true),
+            ]
+        );
+        assert_eq!(diff.to_string(), "~ edit (content, mode)\n~ run (mode)\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_follow_links() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("a", "puppy")
+            .unwrap()
+            .file("b", "puppy")
+            .unwrap()
+            .symlink("link", "a")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("a", "puppy")
+            .unwrap()
+            .file("b", "puppy")
+            .unwrap()
+            .symlink("link", "b")
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+        assert_eq!(diff.to_string(), "");
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().follow_links(false),
+        )?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("a"), DiffTag::Equal),
+                (Path::new("b"), DiffTag::Equal),
+                (Path::new("link"), DiffTag::Replace),
+            ]
+        );
+        assert!(
+            diff.get(Path::new("link"))
+                .unwrap()
+                .deleted()
+                .unwrap()
+                .file_type()
+                == FileType::Symlink
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_change() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("a", "a")
+            .unwrap()
+            .dir("b/c")
+            .unwrap()
+            .file("b/1", "1")
+            .unwrap()
+            .file("b/c/2", "2")
             .unwrap();
 
         let mut new = TempTree::new().unwrap();
@@ -329,16 +685,68 @@ mod tests {
             .unwrap()
             .file("a/1", "1")
             .unwrap()
-            .file("a/2", "2")
+            .file("b", "b")
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("a"), DiffTag::TypeChange),
+                (Path::new("a/1"), DiffTag::Insert),
+                (Path::new("b"), DiffTag::TypeChange),
+                (Path::new("b/1"), DiffTag::Delete),
+                (Path::new("b/c"), DiffTag::Delete),
+            ]
+        );
+        assert_eq!(diff.to_string(), "! a/\n+ a/1\n! b\n- b/1\n- b/c/\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_exclude() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir(".git")
             .unwrap()
-            .dir("b")
+            .file(".git/HEAD", "main")
             .unwrap()
-            .file("b/1", "1x")
+            .dir("src/__pycache__")
             .unwrap()
-            .file("b/2", "2x")
+            .file("src/__pycache__/main.pyc", "1")
+            .unwrap()
+            .file("src/main.py", "1")
+            .unwrap()
+            .file("src/README.md", "1")
             .unwrap();
 
-        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+        let mut new = TempTree::new().unwrap();
+        new.dir(".git")
+            .unwrap()
+            .file(".git/HEAD", "dev")
+            .unwrap()
+            .dir("src/__pycache__")
+            .unwrap()
+            .file("src/__pycache__/main.pyc", "2")
+            .unwrap()
+            .file("src/main.py", "2")
+            .unwrap()
+            .file("src/README.md", "2")
+            .unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new()
+                .include("*.py")
+                .include("*.pyc")
+                .exclude(".git")
+                .exclude("__pycache__"),
+        )?;
 
         assert_eq!(
             (&diff)
@@ -346,7 +754,773 @@ mod tests {
                 .map(DiffEntry::as_pair)
                 .collect::<Vec<_>>(),
             vec![
-                (Path::new("a"), DiffTag::Replace),
+                (Path::new("src"), DiffTag::Replace),
+                (Path::new("src/main.py"), DiffTag::Replace),
+            ]
+        );
+
+        assert!(matches!(
+            Diff::with_opts(old.as_ref(), new.as_ref(), DiffOpts::new().exclude("a[")),
+            Err(Error::Glob(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_dirs() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("deleted")
+            .unwrap()
+            .dir("docs")
+            .unwrap()
+            .dir("nested/docs")
+            .unwrap()
+            .dir("src/docs")
+            .unwrap()
+            .file("deleted/README.md", "1")
+            .unwrap()
+            .file("docs/README.md", "1")
+            .unwrap()
+            .file("nested/docs/README.md", "1")
+            .unwrap()
+            .file("src/main.py", "1")
+            .unwrap()
+            .file("src/docs/README.md", "1")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("docs")
+            .unwrap()
+            .dir("inserted")
+            .unwrap()
+            .dir("nested/docs")
+            .unwrap()
+            .dir("src")
+            .unwrap()
+            .dir("tests")
+            .unwrap()
+            .file("docs/README.md", "2")
+            .unwrap()
+            .file("inserted/README.md", "2")
+            .unwrap()
+            .file("nested/docs/README.md", "2")
+            .unwrap()
+            .file("src/main.py", "1")
+            .unwrap()
+            .file("src/lib.py", "2")
+            .unwrap()
+            .file("tests/test.py", "2")
+            .unwrap();
+
+        let diff = Diff::with_opts(old.as_ref(), new.as_ref(), DiffOpts::new().include("*.py"))?;
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("src"), DiffTag::Replace),
+                (Path::new("src/lib.py"), DiffTag::Insert),
+                (Path::new("src/main.py"), DiffTag::Equal),
+                (Path::new("tests"), DiffTag::Insert),
+            ]
+        );
+        assert_eq!(diff.to_string(), "+ src/lib.py\n+ tests/\n");
+
+        // Directories which match are reported along with their contents.
+        let diff = Diff::with_opts(old.as_ref(), new.as_ref(), DiffOpts::new().include("docs"))?;
+        assert_eq!(
+            diff.to_string(),
+            "~ docs/README.md\n~ nested/docs/README.md\n- src/docs/\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_files() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file(".gitignore", "target/\n*.log\n")
+            .unwrap()
+            .dir("target")
+            .unwrap()
+            .file("target/1", "1")
+            .unwrap()
+            .dir("src")
+            .unwrap()
+            .file("src/.ignore", "!keep.log\n")
+            .unwrap()
+            .file("src/.diffignore", "secret\n")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file(".gitignore", "target/\n*.log\n")
+            .unwrap()
+            .dir("target")
+            .unwrap()
+            .file("target/1", "2")
+            .unwrap()
+            .file("target/2", "2")
+            .unwrap()
+            .file("debug.log", "log")
+            .unwrap()
+            .dir("src")
+            .unwrap()
+            .file("src/.ignore", "!keep.log\n")
+            .unwrap()
+            .file("src/.diffignore", "secret\n")
+            .unwrap()
+            .file("src/debug.log", "log")
+            .unwrap()
+            .file("src/keep.log", "log")
+            .unwrap()
+            .file("src/secret", "secret")
+            .unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new()
+                .ignore_files(true)
+                .custom_ignore_filename(".diffignore"),
+        )?;
+
+        assert_eq!(diff.to_string(), "+ src/keep.log\n");
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().custom_ignore_filename(".diffignore"),
+        )?;
+
+        assert_eq!(
+            diff.to_string(),
+            indoc::indoc!(
+                r#"
+                + debug.log
+                + src/debug.log
+                + src/keep.log
+                ~ target/1
+                + target/2
+                "#
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tolerate_errors() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .symlink("broken", "does-not-exist")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "doggy")
+            .unwrap()
+            .symlink("broken", "does-not-exist")
+            .unwrap();
+
+        assert!(matches!(
+            Diff::new(old.as_ref(), new.as_ref()),
+            Err(Error::Traverse(_))
+        ));
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::new().tolerate_errors(true),
+        )?;
+
+        assert_eq!(diff.to_string(), "~ puppy\n");
+        assert_eq!(diff.errors().len(), 2);
+        assert!(
+            diff.errors()
+                .iter()
+                .all(|err| matches!(err, Error::Traverse(_)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a")
+            .unwrap()
+            .file("a/1", "1")
+            .unwrap()
+            .file("a/2", "2")
+            .unwrap()
+            .file("b", "b")
+            .unwrap()
+            .file("puppy", "puppy")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a")
+            .unwrap()
+            .file("a/1", "1x")
+            .unwrap()
+            .file("a/3", "3")
+            .unwrap()
+            .file("b", "b")
+            .unwrap()
+            .file("doggy", "puppy")
+            .unwrap();
+
+        let mut entries = Vec::new();
+        let errors = Diff::stream(old.as_ref(), new.as_ref(), Default::default(), |entry| {
+            entries.push((entry.relative.clone(), entry.tag));
+            Ok(())
+        })?;
+        assert!(errors.is_empty());
+
+        // Directories come after their contents.
+        assert_eq!(
+            entries,
+            vec![
+                ("a/1".into(), DiffTag::Replace),
+                ("a/2".into(), DiffTag::Delete),
+                ("a/3".into(), DiffTag::Insert),
+                ("a".into(), DiffTag::Replace),
+                ("b".into(), DiffTag::Equal),
+                ("doggy".into(), DiffTag::Insert),
+                ("puppy".into(), DiffTag::Delete),
+            ]
+        );
+
+        // Renames come last.
+        let mut entries = Vec::new();
+        Diff::stream(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::default().detect_renames(true),
+            |entry| {
+                entries.push((entry.relative.clone(), entry.tag));
+                Ok(())
+            },
+        )?;
+        assert_eq!(
+            entries,
+            vec![
+                ("a/1".into(), DiffTag::Replace),
+                ("a".into(), DiffTag::Replace),
+                ("b".into(), DiffTag::Equal),
+                ("a/2".into(), DiffTag::Delete),
+                ("a/3".into(), DiffTag::Insert),
+                ("doggy".into(), DiffTag::Rename),
+            ]
+        );
+
+        // Errors from the callback stop the diff.
+        let mut count = 0;
+        let result = Diff::stream(old.as_ref(), new.as_ref(), Default::default(), |_| {
+            count += 1;
+            Err(StripPrefixError {
+                path: "puppy".into(),
+                prefix: "doggy".into(),
+            }
+            .into())
+        });
+        assert!(matches!(result, Err(Error::StripPrefix(_))));
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_type_change() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("a", "a")
+            .unwrap()
+            .dir("b")
+            .unwrap()
+            .file("b/1", "1")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a")
+            .unwrap()
+            .file("a/1", "1")
+            .unwrap()
+            .file("b", "b")
+            .unwrap();
+
+        for (parallel_walk, parallel_compare) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let opts = DiffOpts::default()
+                .parallel_walk(parallel_walk)
+                .parallel_compare(parallel_compare);
+            let mut entries = Vec::new();
+            Diff::stream(old.as_ref(), new.as_ref(), opts, |entry| {
+                entries.push((entry.relative.clone(), entry.tag));
+                Ok(())
+            })?;
+
+            // Replaced directories also come after their contents.
+            assert_eq!(
+                entries,
+                vec![
+                    ("a/1".into(), DiffTag::Insert),
+                    ("a".into(), DiffTag::TypeChange),
+                    ("b/1".into(), DiffTag::Delete),
+                    ("b".into(), DiffTag::TypeChange),
+                ]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_loop() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a").unwrap().symlink("a/loop", "..").unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a").unwrap().symlink("a/loop", "..").unwrap();
+
+        assert!(matches!(
+            Diff::new(old.as_ref(), new.as_ref()),
+            Err(Error::Loop(_))
+        ));
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::default().tolerate_errors(true),
+        )?;
+        assert_eq!(diff.errors().len(), 2);
+        assert!(
+            diff.errors()
+                .iter()
+                .all(|error| matches!(error, Error::Loop(_)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_loop_renamed() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a").unwrap().symlink("a/loop", "..").unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("b").unwrap().symlink("b/loop", "..").unwrap();
+
+        let opts = DiffOpts::default().detect_renames(true);
+        assert!(matches!(
+            Diff::with_opts(old.as_ref(), new.as_ref(), opts.clone()),
+            Err(Error::Loop(_))
+        ));
+
+        let diff = Diff::with_opts(old.as_ref(), new.as_ref(), opts.tolerate_errors(true))?;
+        // A tree containing a loop can't be hashed, so it isn't detected as a rename.
+        assert_eq!(diff.to_string(), "- a/\n+ b/\n");
+        assert_eq!(diff.errors().len(), 2);
+        assert!(
+            diff.errors()
+                .iter()
+                .all(|error| matches!(error, Error::Loop(_)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_owned() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync + 'static>(_: &T) {}
+
+        fn owned_diff(old: PathBuf, new: PathBuf) -> Result<Diff<'static>> {
+            Ok(Diff::new(&old, &new)?.into_owned())
+        }
+
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .file("doggy", "doggy")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "kitty").unwrap();
+
+        let old_path = AsRef::<Path>::as_ref(&old).to_owned();
+        let new_path = AsRef::<Path>::as_ref(&new).to_owned();
+        let diff = owned_diff(old_path.clone(), new_path.clone())?;
+        assert_send_sync(&diff);
+
+        let display = std::thread::spawn(move || {
+            let puppy = diff.get(Path::new("puppy")).unwrap();
+            assert_eq!(puppy.deleted().unwrap().base(), old_path);
+            assert_eq!(puppy.inserted().unwrap().base(), new_path);
+            diff.to_string()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(display, "- doggy\n~ puppy\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_compare() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a")
+            .unwrap()
+            .file("a/same", "same")
+            .unwrap()
+            .dir("a/b")
+            .unwrap()
+            .file("a/b/puppy", "puppy\n")
+            .unwrap()
+            .file("a/b/longer", "short")
+            .unwrap()
+            .dir("c")
+            .unwrap()
+            .file("c/same", "same")
+            .unwrap()
+            .file("mode", "mode")
+            .unwrap()
+            .mode("mode", 0o644)
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a")
+            .unwrap()
+            .file("a/same", "same")
+            .unwrap()
+            .dir("a/b")
+            .unwrap()
+            .file("a/b/puppy", "kitty\n")
+            .unwrap()
+            .file("a/b/longer", "longer")
+            .unwrap()
+            .dir("c")
+            .unwrap()
+            .file("c/same", "same")
+            .unwrap()
+            .file("mode", "mode")
+            .unwrap()
+            .mode("mode", 0o755)
+            .unwrap();
+
+        let opts = DiffOpts::default()
+            .text_diff(true)
+            .compare_metadata(CompareMetadata::new().mode(true));
+        let sequential = Diff::with_opts(old.as_ref(), new.as_ref(), opts.clone())?;
+        let parallel = Diff::with_opts(old.as_ref(), new.as_ref(), opts.parallel_compare(true))?;
+
+        let summarize = |diff: &Diff<'_>| {
+            diff.iter()
+                .map(|entry| (entry.relative.clone(), entry.tag, entry.changes))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summarize(&parallel), summarize(&sequential));
+        assert_eq!(parallel.to_string(), sequential.to_string());
+        assert_eq!(
+            parallel.to_string(),
+            indoc::indoc!(
+                "
+                ~ a/b/longer
+                @@ -1 +1 @@
+                -short
+                \\ No newline at end of file
+                +longer
+                \\ No newline at end of file
+                ~ a/b/puppy
+                @@ -1 +1 @@
+                -puppy
+                +kitty
+                ~ mode (mode)
+                "
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_walk() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        let mut new = TempTree::new().unwrap();
+        for dir in ["a", "b", "c", "d"] {
+            old.dir(dir).unwrap();
+            new.dir(dir).unwrap();
+            for file in ["1", "2", "3"] {
+                let path = format!("{dir}/{file}");
+                old.file(&path, &path).unwrap();
+                if file != "3" {
+                    new.file(&path, format!("{path}{dir}")).unwrap();
+                }
+            }
+            new.file(format!("{dir}/4"), "4").unwrap();
+            old.symlink(format!("{dir}/broken"), "nowhere").unwrap();
+        }
+        old.dir("e").unwrap().file("e/1", "1").unwrap();
+        new.file("e", "e").unwrap();
+
+        /// Fails to compare files named `2`, so that comparison errors are interleaved with
+        /// errors from walking the trees.
+        struct FailingComparator;
+
+        impl Comparator for FailingComparator {
+            fn is_same(
+                &self,
+                relative: &Path,
+                old: &PathInfo<'_>,
+                new: &PathInfo<'_>,
+            ) -> Result<bool> {
+                if relative.ends_with("2") {
+                    let path = old.base().join(relative);
+                    return Err(CompareError::new(path, std::io::Error::other("Failed")).into());
+                }
+                Blake3Comparator.is_same(relative, old, new)
+            }
+        }
+
+        let stream = |opts: DiffOpts| -> Result<_> {
+            let mut entries = Vec::new();
+            let errors = Diff::stream(
+                old.as_ref(),
+                new.as_ref(),
+                opts.tolerate_errors(true).comparator(FailingComparator),
+                |entry| {
+                    entries.push((entry.relative.clone(), entry.tag, entry.changes));
+                    Ok(())
+                },
+            )?;
+            let errors = errors
+                .into_iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>();
+            Ok((entries, errors))
+        };
+
+        let sequential = stream(DiffOpts::default())?;
+        assert_eq!(sequential.0.len(), 22);
+        assert_eq!(sequential.1.len(), 8);
+
+        assert_eq!(stream(DiffOpts::default().parallel_walk(true))?, sequential);
+        assert_eq!(
+            stream(
+                DiffOpts::default()
+                    .parallel_walk(true)
+                    .parallel_compare(true)
+            )?,
+            sequential
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quick_check() -> Result<()> {
+        let past = SystemTime::now() - Duration::from_secs(600);
+
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap()
+            .file("doggy", "doggy")
+            .unwrap()
+            .mtime("doggy", past)
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "kitty")
+            .unwrap()
+            .mtime("puppy", past)
+            .unwrap()
+            .file("doggy", "kitty")
+            .unwrap();
+
+        let diff = Diff::with_opts(
+            old.as_ref(),
+            new.as_ref(),
+            DiffOpts::default().quick_check(true),
+        )?;
+        // `doggy`'s mtime changed, so it's compared.
+        assert_eq!(diff.to_string(), "~ doggy\n");
+
+        let puppy = diff.get(Path::new("puppy")).unwrap();
+        assert_eq!(puppy.tag, DiffTag::Equal);
+        assert!(puppy.assumed_equal());
+        assert_eq!(puppy.deleted().unwrap().hash(), None);
+        assert!(!diff.get(Path::new("doggy")).unwrap().assumed_equal());
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+        assert_eq!(diff.to_string(), "~ doggy\n~ puppy\n");
+        assert!(!diff.get(Path::new("puppy")).unwrap().assumed_equal());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_tree() -> Result<()> {
+        use std::collections::BTreeMap;
+        use std::io::Read;
+
+        /// Files (with their contents) and directories (without), indexed by path.
+        #[derive(Debug)]
+        struct MapTree {
+            root: PathBuf,
+            entries: BTreeMap<PathBuf, Option<&'static str>>,
+        }
+
+        impl Tree for MapTree {
+            fn root(&self) -> &Path {
+                &self.root
+            }
+
+            fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+                // Children don't need to be sorted.
+                Ok(self
+                    .entries
+                    .keys()
+                    .rev()
+                    .filter(|path| path.parent() == Some(relative))
+                    .map(|path| TreeEntry::new(path.file_name().unwrap(), self.stat(path)))
+                    .collect())
+            }
+
+            fn stat(&self, relative: &Path) -> Result<Stat> {
+                match self.entries.get(relative) {
+                    _ if relative.as_os_str().is_empty() => Ok(Stat::new(FileType::Dir, 0)),
+                    Some(Some(contents)) if *contents == "broken" => {
+                        Err(TreeError::new(self.root.join(relative), "Broken").into())
+                    }
+                    Some(Some(contents)) => Ok(Stat::new(FileType::File, contents.len() as u64)
+                        .with_hash(blake3::hash(contents.as_bytes()))),
+                    Some(None) => Ok(Stat::new(FileType::Dir, 0).with_mode(0o755)),
+                    None => Err(TreeError::new(self.root.join(relative), "Not found").into()),
+                }
+            }
+
+            fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+                Err(TreeError::new(self.root.join(relative), "Not a symbolic link").into())
+            }
+
+            fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+                Ok(self.entries[relative].map(|contents| Box::new(contents.as_bytes()) as _))
+            }
+        }
+
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap();
+
+        let mut entries = BTreeMap::from([
+            (PathBuf::from("a"), None),
+            (PathBuf::from("a/puppy"), Some("puppy\n")),
+            (PathBuf::from("doggy"), Some("kitty\n")),
+            (PathBuf::from("new"), Some("new\n")),
+        ]);
+        let memory = MapTree {
+            root: PathBuf::from("memory"),
+            entries: entries.clone(),
+        };
+        let diff = Diff::with_sources(
+            Source::dir(tree.as_ref()),
+            Source::tree(memory),
+            DiffOpts::new().text_diff(true),
+        )?;
+        assert_eq!(
+            diff.to_string(),
+            indoc::indoc!(
+                "
+                ~ doggy
+                @@ -1 +1 @@
+                -doggy
+                +kitty
+                + new
+                "
+            )
+        );
+        // Hashes recorded by the tree are used as they are.
+        let puppy = diff.get(Path::new("a/puppy")).unwrap();
+        assert_eq!(puppy.tag(), DiffTag::Equal);
+        assert_eq!(
+            puppy.inserted().unwrap().stat().hash(),
+            Some(blake3::hash(b"puppy\n"))
+        );
+        assert_eq!(puppy.inserted().unwrap().base(), Path::new("memory"));
+        assert_eq!(puppy.inserted().unwrap().mode(), None);
+        assert!(puppy.inserted().unwrap().metadata().is_none());
+        assert!(puppy.deleted().unwrap().metadata().unwrap().is_file());
+
+        entries.insert(PathBuf::from("broken"), Some("broken"));
+        let memory = MapTree {
+            root: PathBuf::from("memory"),
+            entries,
+        };
+        let error = Diff::with_sources(
+            Source::dir(tree.as_ref()),
+            Source::tree(memory),
+            DiffOpts::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(&error, Error::Tree(inner) if inner.path() == Path::new("memory/broken")));
+        assert_eq!(error.to_string(), "Failed to read `memory/broken`: Broken");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_complex() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a")
+            .unwrap()
+            .file("a/1", "1")
+            .unwrap()
+            .file("a/2", "2")
+            .unwrap()
+            .dir("b")
+            .unwrap()
+            .file("b/1", "1")
+            .unwrap()
+            .file("b/2", "2")
+            .unwrap()
+            .dir("c")
+            .unwrap()
+            .file("c/1", "1")
+            .unwrap()
+            .file("c/2", "2")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a")
+            .unwrap()
+            .file("a/1", "1")
+            .unwrap()
+            .file("a/2", "2")
+            .unwrap()
+            .dir("b")
+            .unwrap()
+            .file("b/1", "1x")
+            .unwrap()
+            .file("b/2", "2x")
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+
+        assert_eq!(
+            (&diff)
+                .into_iter()
+                .map(DiffEntry::as_pair)
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("a"), DiffTag::Equal),
                 (Path::new("a/1"), DiffTag::Equal),
                 (Path::new("a/2"), DiffTag::Equal),
                 (Path::new("b"), DiffTag::Replace),
@@ -0,0 +1,124 @@
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+
+use crate::Error;
+use crate::HashCache;
+use crate::MetadataError;
+use crate::ReadError;
+use crate::Result;
+use crate::TraverseError;
+use crate::WalkDirMetadataError;
+use crate::hash_file::hash_file;
+use crate::read_link::read_link;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// A directory on the local filesystem.
+#[derive(Debug)]
+pub(crate) struct LocalTree {
+    root: PathBuf,
+    follow_links: bool,
+    hash_cache: Option<HashCache>,
+}
+
+impl LocalTree {
+    pub(crate) fn new(root: &Path, follow_links: bool, hash_cache: Option<HashCache>) -> Self {
+        Self {
+            root: root.to_owned(),
+            follow_links,
+            hash_cache,
+        }
+    }
+}
+
+impl Tree for LocalTree {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        let path = self.root.join(relative);
+        let walker = WalkDir::new(&path)
+            .follow_links(self.follow_links)
+            .min_depth(1)
+            .max_depth(1)
+            .sort_by_file_name();
+
+        let mut entries = Vec::new();
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(inner) => {
+                    let name = inner
+                        .path()
+                        .filter(|child| *child != path)
+                        .and_then(Path::file_name)
+                        .map(ToOwned::to_owned);
+                    let err = Error::from(TraverseError {
+                        path: self.root.clone(),
+                        inner,
+                    });
+                    match name {
+                        Some(name) => {
+                            entries.push(TreeEntry {
+                                name,
+                                stat: Err(err),
+                            });
+                            continue;
+                        }
+                        None => return Err(err),
+                    }
+                }
+            };
+
+            let stat = entry.metadata().map(Stat::from_metadata).map_err(|inner| {
+                Error::from(WalkDirMetadataError {
+                    path: entry.path().to_owned(),
+                    inner,
+                })
+            });
+            entries.push(TreeEntry {
+                name: entry.file_name().to_owned(),
+                stat,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        let path = self.root.join(relative);
+        // The root is always followed, like `walkdir` does.
+        let metadata = if self.follow_links || relative.as_os_str().is_empty() {
+            path.metadata()
+        } else {
+            path.symlink_metadata()
+        };
+        Ok(metadata
+            .map(Stat::from_metadata)
+            .map_err(|inner| MetadataError { path, inner })?)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        read_link(self.root.join(relative))
+    }
+
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        let path = self.root.join(relative);
+        tracing::trace!("Reading {path:?}");
+        let file = std::fs::File::open(&path).map_err(|inner| ReadError { path, inner })?;
+        Ok(Some(Box::new(file)))
+    }
+
+    fn hash_file(&self, relative: &Path, stat: &Stat) -> Result<blake3::Hash> {
+        let path = self.root.join(relative);
+        match (&self.hash_cache, &stat.metadata) {
+            (Some(cache), Some(metadata)) => cache.hash_file(&path, metadata),
+            _ => hash_file(path),
+        }
+    }
+}
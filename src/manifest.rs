@@ -0,0 +1,666 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::io::Read;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::DiffOpts;
+use crate::FileType;
+use crate::HashError;
+use crate::LoopError;
+use crate::ManifestError;
+use crate::MetadataError;
+use crate::ReadError;
+use crate::ReadLinkError;
+use crate::Result;
+use crate::SaveManifestError;
+use crate::dir_stack::DirStack;
+use crate::escape::escape_path;
+use crate::escape::unescape_path;
+use crate::filter::Filter;
+use crate::local_tree::LocalTree;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// The first line of every manifest, which identifies the format and its version.
+const HEADER: &str = "diff-trees manifest v1";
+
+/// A record of the paths in a directory tree, which can be diffed against a directory with
+/// [`crate::Source::manifest`] instead of keeping a copy of the whole tree around.
+///
+/// Record a directory with [`Manifest::from_dir`] and save it with [`Manifest::save`], then load
+/// it later with [`Manifest::load`].
+///
+/// # Format
+///
+/// Manifests are text files. The first line is `diff-trees manifest v1`, and each following line
+/// describes one path with these tab-separated fields:
+///
+/// 1. The type of the path: `file`, `dir`, `symlink`, or `other`.
+/// 2. The permission bits, in octal.
+/// 3. The size in bytes.
+/// 4. The [`blake3`] hash of the contents in hex, for files. This is empty for other paths.
+/// 5. The path, relative to the root of the tree.
+/// 6. For symbolic links only, the target of the link.
+///
+/// Paths and link targets are escaped so that they fit on one line: backslashes are doubled, and
+/// control characters (including tabs and newlines) and bytes which aren't valid UTF-8 are
+/// written as `\xNN`. Directories must be listed before their contents.
+///
+/// The format is stable: manifests written by this version of the crate will be readable by any
+/// later version. Incompatible changes will bump the version in the first line.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// The file the manifest was loaded from, or empty.
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, ManifestEntry>,
+    /// The names of the children of each directory.
+    children: HashMap<PathBuf, BTreeSet<OsString>>,
+}
+
+/// A path recorded in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub(crate) file_type: FileType,
+    pub(crate) mode: u32,
+    pub(crate) size: u64,
+    pub(crate) hash: Option<blake3::Hash>,
+    pub(crate) target: Option<PathBuf>,
+}
+
+impl ManifestEntry {
+    /// The type of the path.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// The permission bits of the path.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The size of the path in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The [`blake3`] hash of the file's contents, for files.
+    pub fn hash(&self) -> Option<blake3::Hash> {
+        self.hash
+    }
+
+    /// The target of the symbolic link, for symbolic links.
+    pub fn target(&self) -> Option<&Path> {
+        self.target.as_deref()
+    }
+
+    fn stat(&self) -> Stat {
+        Stat {
+            mode: Some(self.mode),
+            ..Stat::new(self.file_type, self.size)
+        }
+    }
+}
+
+impl Manifest {
+    /// Record the contents of the directory at `path`.
+    ///
+    /// Paths are walked and hashed the same way as [`crate::Diff::new`].
+    pub fn from_dir(path: &Path) -> Result<Self> {
+        Self::from_dir_with_opts(path, Default::default())
+    }
+
+    /// Record the contents of the directory at `path` with the given options.
+    ///
+    /// [`DiffOpts::follow_links`], [`DiffOpts::hash_cache`], and the options which restrict which
+    /// paths are walked (like [`DiffOpts::exclude`]) are respected, and other options are ignored.
+    /// Errors always stop the walk, even with [`DiffOpts::tolerate_errors`], because paths missing
+    /// from a manifest would later be reported as deleted.
+    pub fn from_dir_with_opts(path: &Path, opts: DiffOpts) -> Result<Self> {
+        let tree = LocalTree::new(path, opts.follow_links, opts.hash_cache.clone());
+        let filter = Filter::new(&opts)?;
+        let root = Path::new("");
+        let dirs =
+            DirStack::default().push(&tree, root, &tree.stat(root)?, filter.ignore_filenames());
+
+        let mut manifest = Self {
+            path: path.to_owned(),
+            ..Default::default()
+        };
+        manifest.add_dir(&tree, &filter, opts.follow_links, root, &dirs)?;
+        Ok(manifest)
+    }
+
+    /// Record the contents of the directory at `relative`.
+    fn add_dir(
+        &mut self,
+        tree: &LocalTree,
+        filter: &Filter,
+        follow_links: bool,
+        relative: &Path,
+        dirs: &DirStack,
+    ) -> Result<()> {
+        for child in tree.read_dir(relative)? {
+            let relative = relative.join(&child.name);
+            // Paths which are filtered out aren't read, like when diffing.
+            let is_dir = child.stat.as_ref().is_ok_and(Stat::is_dir);
+            if !filter.is_match(&relative, is_dir) || dirs.is_ignored(&relative, is_dir) {
+                continue;
+            }
+            let stat = child.stat?;
+
+            let entry = ManifestEntry {
+                file_type: stat.file_type,
+                mode: stat.mode.unwrap_or_default(),
+                size: stat.size,
+                hash: stat
+                    .is_file()
+                    .then(|| tree.hash_file(&relative, &stat))
+                    .transpose()?,
+                target: stat
+                    .is_symlink()
+                    .then(|| tree.read_link(&relative))
+                    .transpose()?,
+            };
+            self.insert(relative.clone(), entry)
+                .expect("Directories are walked before their contents");
+
+            if stat.is_dir() {
+                if follow_links && dirs.contains(&stat) {
+                    return Err(LoopError {
+                        path: tree.root().join(&relative),
+                    }
+                    .into());
+                }
+                let dirs = dirs.push(tree, &relative, &stat, filter.ignore_filenames());
+                self.add_dir(tree, filter, follow_links, &relative, &dirs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the manifest to a file, in the format described in [`Manifest`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_string()).map_err(|inner| SaveManifestError {
+            path: path.to_owned(),
+            inner,
+        })?;
+        Ok(())
+    }
+
+    /// Load a manifest from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|inner| ReadError {
+            path: path.to_owned(),
+            inner,
+        })?;
+        let mut manifest = Self::parse_inner(&contents, Some(path))?;
+        manifest.path = path.to_owned();
+        Ok(manifest)
+    }
+
+    /// Parse a manifest from a string.
+    pub fn parse(contents: &str) -> Result<Self> {
+        Self::parse_inner(contents, None)
+    }
+
+    fn parse_inner(contents: &str, path: Option<&Path>) -> Result<Self> {
+        let error = |line: usize, message: String| ManifestError {
+            path: path.map(ToOwned::to_owned),
+            line,
+            message,
+        };
+
+        let mut lines = contents.lines().zip(1..);
+        match lines.next() {
+            Some((HEADER, _)) => {}
+            Some((header, _)) if header.starts_with("diff-trees manifest ") => {
+                return Err(error(1, format!("Unsupported manifest version `{header}`")).into());
+            }
+            _ => return Err(error(1, format!("Expected `{HEADER}`")).into()),
+        }
+
+        let mut manifest = Self::default();
+        for (line, number) in lines {
+            let (relative, entry) = parse_line(line).map_err(|message| error(number, message))?;
+            manifest
+                .insert(relative, entry)
+                .map_err(|message| error(number, message))?;
+        }
+        Ok(manifest)
+    }
+
+    /// Add an entry to the manifest. Its parent directory must already be present.
+    pub(crate) fn insert(
+        &mut self,
+        relative: PathBuf,
+        entry: ManifestEntry,
+    ) -> std::result::Result<(), String> {
+        if relative.as_os_str().is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!("Invalid path {relative:?}"));
+        }
+        if self.entries.contains_key(&relative) {
+            return Err(format!("Duplicate path {relative:?}"));
+        }
+
+        let parent = relative.parent().unwrap_or(Path::new(""));
+        if !parent.as_os_str().is_empty()
+            && self.entries.get(parent).map(|entry| entry.file_type) != Some(FileType::Dir)
+        {
+            return Err(format!("Parent directory of {relative:?} is not listed"));
+        }
+
+        let name = relative
+            .file_name()
+            .expect("Normal paths have file names")
+            .to_owned();
+        self.children
+            .entry(parent.to_owned())
+            .or_default()
+            .insert(name);
+        self.entries.insert(relative, entry);
+        Ok(())
+    }
+
+    /// The file this manifest was loaded from or the directory it was recorded from, or an empty
+    /// path if it was parsed from a string.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the entry for the path at `relative`.
+    pub fn get(&self, relative: &Path) -> Option<&ManifestEntry> {
+        self.entries.get(relative)
+    }
+
+    /// Iterate over the paths in the manifest and their entries, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &ManifestEntry)> {
+        self.entries
+            .iter()
+            .map(|(relative, entry)| (relative.as_path(), entry))
+    }
+
+    /// The number of paths in the manifest.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the manifest has no paths.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry(&self, relative: &Path) -> std::io::Result<&ManifestEntry> {
+        self.entries.get(relative).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Path not found in manifest")
+        })
+    }
+}
+
+/// Formatted as described in [`Manifest`], so that it can be read back with [`Manifest::parse`].
+impl Display for Manifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for (relative, entry) in &self.entries {
+            write!(f, "{}\t{:o}\t{}\t", entry.file_type, entry.mode, entry.size)?;
+            if let Some(hash) = entry.hash {
+                write!(f, "{hash}")?;
+            }
+            write!(f, "\t{}", escape_path(relative))?;
+            if let Some(target) = &entry.target {
+                write!(f, "\t{}", escape_path(target))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> std::result::Result<(PathBuf, ManifestEntry), String> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    let [file_type, mode, size, hash, path, rest @ ..] = fields.as_slice() else {
+        return Err(format!("Expected at least 5 fields, got {}", fields.len()));
+    };
+
+    let file_type =
+        FileType::from_str(file_type).ok_or_else(|| format!("Invalid type `{file_type}`"))?;
+    let mode = u32::from_str_radix(mode, 8).map_err(|_| format!("Invalid mode `{mode}`"))?;
+    let size = size.parse().map_err(|_| format!("Invalid size `{size}`"))?;
+    let hash = match (file_type, *hash) {
+        (FileType::File, hash) => {
+            Some(blake3::Hash::from_hex(hash).map_err(|_| format!("Invalid hash `{hash}`"))?)
+        }
+        (_, "") => None,
+        (_, _) => return Err("Only files have hashes".to_owned()),
+    };
+    let relative = unescape_path(path).ok_or_else(|| format!("Invalid path `{path}`"))?;
+    let target = match (file_type, rest) {
+        (FileType::Symlink, [target]) => {
+            Some(unescape_path(target).ok_or_else(|| format!("Invalid target `{target}`"))?)
+        }
+        (FileType::Symlink, _) => return Err("Expected a symbolic link target".to_owned()),
+        (_, []) => None,
+        (_, _) => return Err("Only symbolic links have targets".to_owned()),
+    };
+
+    Ok((
+        relative,
+        ManifestEntry {
+            file_type,
+            mode,
+            size,
+            hash,
+            target,
+        },
+    ))
+}
+
+impl Tree for Manifest {
+    fn root(&self) -> &Path {
+        &self.path
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        Ok(self
+            .children
+            .get(relative)
+            .into_iter()
+            .flatten()
+            .map(|name| TreeEntry {
+                name: name.clone(),
+                stat: Ok(self.entries[&relative.join(name)].stat()),
+            })
+            .collect())
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        if relative.as_os_str().is_empty() {
+            return Ok(Stat::new(FileType::Dir, 0));
+        }
+        Ok(self
+            .entry(relative)
+            .map(ManifestEntry::stat)
+            .map_err(|inner| MetadataError {
+                path: self.path.join(relative),
+                inner,
+            })?)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        let target = self.entry(relative).and_then(|entry| {
+            entry.target.clone().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a symbolic link")
+            })
+        });
+        Ok(target.map_err(|inner| ReadLinkError {
+            path: self.path.join(relative),
+            inner,
+        })?)
+    }
+
+    fn open(&self, _relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        Ok(None)
+    }
+
+    fn hash_file(&self, relative: &Path, _stat: &Stat) -> Result<blake3::Hash> {
+        let hash = self.entry(relative).and_then(|entry| {
+            entry
+                .hash
+                .ok_or_else(|| std::io::Error::other("No hash recorded in manifest"))
+        });
+        Ok(hash.map_err(|inner| HashError {
+            path: self.path.join(relative),
+            inner,
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::Diff;
+    use crate::Error;
+    use crate::Source;
+
+    #[test]
+    fn test_manifest() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy")
+            .unwrap()
+            .file("doggy", "kitty")
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap()
+            .file("new", "new")
+            .unwrap();
+
+        let manifests = TempTree::new().unwrap();
+        let manifest_path = manifests.child("manifest");
+        std::fs::write(
+            &manifest_path,
+            format!(
+                indoc::indoc! {"
+                    diff-trees manifest v1
+                    dir\t755\t0\t\ta
+                    file\t644\t5\t{}\ta/puppy
+                    file\t644\t5\t{}\tdoggy
+                    file\t644\t4\t{}\tgone
+                    symlink\t777\t7\t\tlink\ta/puppy
+                "},
+                blake3::hash(b"puppy"),
+                blake3::hash(b"doggy"),
+                blake3::hash(b"gone"),
+            ),
+        )
+        .unwrap();
+
+        let manifest = Manifest::load(&manifest_path)?;
+        assert_eq!(manifest.len(), 5);
+        assert_eq!(
+            manifest.get(Path::new("link")).unwrap().target(),
+            Some(Path::new("a/puppy"))
+        );
+
+        let diff = Diff::with_sources(
+            manifest.into(),
+            Source::dir(tree.as_ref()),
+            DiffOpts::new().follow_links(false),
+        )?;
+        assert_eq!(diff.to_string(), "~ doggy\n- gone\n+ new\n");
+
+        let gone = diff.get(Path::new("gone")).unwrap().deleted().unwrap();
+        assert_eq!(gone.base(), manifest_path);
+        assert_eq!(gone.mtime(), None);
+        assert_eq!(gone.mode(), Some(0o644));
+        assert_eq!(gone.uid(), None);
+
+        let error = Manifest::parse("diff-trees manifest v1\nfile\t644\t5\t\tpuppy\n").unwrap_err();
+        assert!(matches!(&error, Error::Manifest(inner) if inner.line() == 2));
+
+        let error = Manifest::parse("diff-trees manifest v1\ndir\t755\t0\t\ta/b\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid manifest on line 2: Parent directory of \"a/b\" is not listed"
+        );
+
+        assert!(Manifest::parse("diff-trees manifest v2\n").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_from_dir() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy")
+            .unwrap()
+            .mode("a/puppy", 0o600)
+            .unwrap()
+            .file("tab\tname", "doggy")
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap()
+            .file(".gitignore", "ignored\n")
+            .unwrap()
+            .file("ignored", "ignored")
+            .unwrap();
+        let opts = DiffOpts::new().follow_links(false).ignore_files(true);
+
+        let manifest = Manifest::from_dir_with_opts(tree.as_ref(), opts.clone())?;
+        assert_eq!(manifest.len(), 5);
+        assert_eq!(manifest.get(Path::new("ignored")), None);
+
+        let puppy = manifest.get(Path::new("a/puppy")).unwrap();
+        assert_eq!(puppy.file_type(), FileType::File);
+        assert_eq!(puppy.mode(), 0o600);
+        assert_eq!(puppy.size(), 5);
+        assert_eq!(puppy.hash(), Some(blake3::hash(b"puppy")));
+
+        let text = manifest.to_string();
+        assert!(text.starts_with("diff-trees manifest v1\nfile\t"));
+        assert!(text.contains(&format!(
+            "file\t600\t5\t{}\ta/puppy\n",
+            blake3::hash(b"puppy")
+        )));
+        assert!(text.contains("\ttab\\x09name\n"));
+        assert!(text.contains("\tlink\ta/puppy\n"));
+
+        let manifests = TempTree::new().unwrap();
+        let manifest_path = manifests.child("manifest");
+        manifest.save(&manifest_path)?;
+        let loaded = Manifest::load(&manifest_path)?;
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            manifest.iter().collect::<Vec<_>>()
+        );
+
+        let diff = Diff::with_sources(loaded.into(), Source::dir(tree.as_ref()), opts.clone())?;
+        assert_eq!(diff.to_string(), "");
+        drop(diff);
+
+        tree.file("a/puppy", "kitty").unwrap();
+        let diff = Diff::with_sources(manifest.into(), Source::dir(tree.as_ref()), opts)?;
+        assert_eq!(diff.to_string(), "~ a/puppy\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_parse_errors() {
+        let hash = blake3::hash(b"puppy");
+        for (contents, line, message) in [
+            ("", 1, format!("Expected `{HEADER}`")),
+            ("puppy\n", 1, format!("Expected `{HEADER}`")),
+            (
+                "diff-trees manifest v2\n",
+                1,
+                "Unsupported manifest version `diff-trees manifest v2`".to_owned(),
+            ),
+            (
+                "file\t644\t5\tpuppy",
+                2,
+                "Expected at least 5 fields, got 4".to_owned(),
+            ),
+            ("pipe\t644\t0\t\tpuppy", 2, "Invalid type `pipe`".to_owned()),
+            ("dir\t999\t0\t\tpuppy", 2, "Invalid mode `999`".to_owned()),
+            ("dir\t755\t-1\t\tpuppy", 2, "Invalid size `-1`".to_owned()),
+            (
+                "file\t644\t5\tpuppy\tpuppy",
+                2,
+                "Invalid hash `puppy`".to_owned(),
+            ),
+            (
+                &format!("dir\t755\t0\t{hash}\tpuppy"),
+                2,
+                "Only files have hashes".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\tpup\\py",
+                2,
+                "Invalid path `pup\\py`".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\t../puppy",
+                2,
+                "Invalid path \"../puppy\"".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\t/puppy",
+                2,
+                "Invalid path \"/puppy\"".to_owned(),
+            ),
+            (
+                "symlink\t777\t5\t\tpuppy",
+                2,
+                "Expected a symbolic link target".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\tpuppy\tdoggy",
+                2,
+                "Only symbolic links have targets".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\tpuppy\nfile\t644\t0\t\tpuppy",
+                3,
+                "Invalid hash ``".to_owned(),
+            ),
+            (
+                "dir\t755\t0\t\tpuppy\ndir\t755\t0\t\tpuppy",
+                3,
+                "Duplicate path \"puppy\"".to_owned(),
+            ),
+            (
+                &format!("file\t644\t5\t{hash}\tpuppy\nfile\t644\t5\t{hash}\tpuppy/doggy"),
+                3,
+                "Parent directory of \"puppy/doggy\" is not listed".to_owned(),
+            ),
+        ] {
+            let contents = match line {
+                1 => contents.to_owned(),
+                _ => format!("{HEADER}\n{contents}\n"),
+            };
+            let error = Manifest::parse(&contents).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid manifest on line {line}: {message}"),
+                "{contents:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_manifest_from_dir_filtered() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.file("puppy", "puppy")
+            .unwrap()
+            .symlink("broken", "nowhere")
+            .unwrap();
+
+        // Paths which are filtered out aren't read, so they can't cause errors.
+        assert!(Manifest::from_dir(tree.as_ref()).is_err());
+        let manifest =
+            Manifest::from_dir_with_opts(tree.as_ref(), DiffOpts::new().exclude("broken"))?;
+        assert_eq!(
+            manifest.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            vec![Path::new("puppy")]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::FileType;
+use crate::Result;
+use crate::archive_index::ArchiveEntry;
+use crate::archive_index::ArchiveIndex;
+use crate::archive_index::normalize;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// A tree held in memory, built up in code.
+///
+/// This is useful for describing an expected tree in tests and diffing it against a directory
+/// (or another [`MemoryTree`]) without touching the disk. Convert it into a [`crate::Source`] to
+/// diff it.
+///
+/// Parent directories are added implicitly. Attributes which aren't set (like modes and
+/// modification times) are `None`, as described in [`Stat`].
+#[derive(Debug, Clone)]
+pub struct MemoryTree {
+    index: ArchiveIndex,
+    /// The contents of the files in the tree, indexed by [`ArchiveEntry::index`].
+    contents: Vec<Vec<u8>>,
+}
+
+impl MemoryTree {
+    /// Construct an empty tree.
+    ///
+    /// `root` doesn't need to exist; it's used in error messages and as
+    /// [`crate::PathInfo::base`].
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            index: ArchiveIndex::new(&root.into()),
+            contents: Vec::new(),
+        }
+    }
+
+    /// Add a directory at `path`, if there isn't one already.
+    ///
+    /// # Panics
+    ///
+    /// If `path` is absolute or contains `..`, or if it or one of its parents is already a file
+    /// or symbolic link.
+    pub fn dir(mut self, path: impl AsRef<Path>) -> Self {
+        let relative = self.check_path(path.as_ref());
+        match self.index.get(&relative) {
+            Some(entry) if entry.stat.is_dir() => {}
+            Some(_) => panic!("{relative:?} is already in the tree and isn't a directory"),
+            None => self
+                .index
+                .insert(relative, ArchiveEntry::new(Stat::new(FileType::Dir, 0))),
+        }
+        self
+    }
+
+    /// Add a file at `path` with the given contents, replacing any file or symbolic link which
+    /// is already there.
+    ///
+    /// # Panics
+    ///
+    /// If `path` is absolute or contains `..`, if it's already a directory, or if one of its
+    /// parents is already a file or symbolic link.
+    pub fn file(mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        let relative = self.check_path(path.as_ref());
+        let contents = contents.into();
+        let stat = Stat::new(FileType::File, contents.len() as u64);
+        let index = match self.replace(&relative).and_then(|entry| entry.index) {
+            Some(index) => {
+                self.contents[index] = contents;
+                index
+            }
+            None => {
+                self.contents.push(contents);
+                self.contents.len() - 1
+            }
+        };
+        self.index.insert(
+            relative,
+            ArchiveEntry {
+                index: Some(index),
+                ..ArchiveEntry::new(stat)
+            },
+        );
+        self
+    }
+
+    /// Add a symbolic link at `path` pointing to `target`, replacing any file or symbolic link
+    /// which is already there.
+    ///
+    /// # Panics
+    ///
+    /// If `path` is absolute or contains `..`, if it's already a directory, or if one of its
+    /// parents is already a file or symbolic link.
+    pub fn symlink(mut self, path: impl AsRef<Path>, target: impl Into<PathBuf>) -> Self {
+        let relative = self.check_path(path.as_ref());
+        self.replace(&relative);
+        let target = target.into();
+        let stat = Stat::new(FileType::Symlink, target.as_os_str().len() as u64);
+        self.index.insert(
+            relative,
+            ArchiveEntry {
+                target: Some(target),
+                ..ArchiveEntry::new(stat)
+            },
+        );
+        self
+    }
+
+    /// Set the permission bits of the path at `path`, like `0o644`.
+    ///
+    /// # Panics
+    ///
+    /// If there's nothing at `path`.
+    pub fn mode(mut self, path: impl AsRef<Path>, mode: u32) -> Self {
+        let entry = self.entry_mut(path.as_ref());
+        entry.stat = entry.stat.clone().with_mode(mode);
+        self
+    }
+
+    /// Set the modification time of the path at `path`.
+    ///
+    /// # Panics
+    ///
+    /// If there's nothing at `path`.
+    pub fn mtime(mut self, path: impl AsRef<Path>, mtime: SystemTime) -> Self {
+        let entry = self.entry_mut(path.as_ref());
+        entry.stat = entry.stat.clone().with_mtime(mtime);
+        self
+    }
+
+    /// Normalize `path`, and check that its parents aren't files or symbolic links.
+    fn check_path(&self, path: &Path) -> PathBuf {
+        let relative = normalize(path)
+            .filter(|relative| !path.is_absolute() && !relative.as_os_str().is_empty())
+            .unwrap_or_else(|| panic!("{path:?} isn't a relative path inside the tree"));
+        for parent in relative.ancestors().skip(1) {
+            if let Some(entry) = self.index.get(parent)
+                && !entry.stat.is_dir()
+            {
+                panic!("Parent {parent:?} of {relative:?} isn't a directory");
+            }
+        }
+        relative
+    }
+
+    /// Check that a file or symbolic link can replace whatever is at `relative`, and return it.
+    fn replace(&self, relative: &Path) -> Option<ArchiveEntry> {
+        let entry = self.index.get(relative)?;
+        if entry.stat.is_dir() {
+            panic!("{relative:?} is already a directory");
+        }
+        Some(entry.clone())
+    }
+
+    fn entry_mut(&mut self, path: &Path) -> &mut ArchiveEntry {
+        let relative = normalize(path).unwrap_or_default();
+        self.index
+            .get_mut(&relative)
+            .unwrap_or_else(|| panic!("{path:?} isn't in the tree"))
+    }
+}
+
+impl Tree for MemoryTree {
+    fn root(&self) -> &Path {
+        self.index.path()
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        Ok(self.index.read_dir(relative))
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        self.index.stat(relative)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        self.index.read_link(relative)
+    }
+
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        let index = self.index.member(relative)?;
+        Ok(Some(Box::new(self.contents[index].as_slice())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::CompareMetadata;
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::DiffTag;
+    use crate::Source;
+
+    #[test]
+    fn test_memory_tree() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .mode("a/puppy", 0o644)
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap()
+            .mode("doggy", 0o644)
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap();
+
+        let expected = MemoryTree::new("expected")
+            .file("a/puppy", "puppy\n")
+            .file("doggy", "doggy\n")
+            .mode("doggy", 0o755)
+            .symlink("link", "a/puppy")
+            .dir("empty");
+        let opts = DiffOpts::new()
+            .follow_links(false)
+            .compare_metadata(CompareMetadata::new().mode(true));
+        let diff = Diff::with_sources(
+            Source::dir(tree.as_ref()),
+            expected.clone().into(),
+            opts.clone(),
+        )?;
+        assert_eq!(diff.to_string(), "~ doggy (mode)\n+ empty/\n");
+        let puppy = diff.get(Path::new("a/puppy")).unwrap();
+        assert_eq!(puppy.tag(), DiffTag::Equal);
+        assert_eq!(puppy.inserted().unwrap().base(), Path::new("expected"));
+        // Modes which aren't set are never considered changed.
+        assert_eq!(puppy.inserted().unwrap().mode(), None);
+
+        let actual = expected
+            .clone()
+            .file("doggy", "kitty\n")
+            .symlink("link", "doggy")
+            .file("empty/new", "new\n");
+        let diff = Diff::with_sources(expected.into(), actual.into(), opts)?;
+        assert_eq!(diff.to_string(), "~ doggy\n+ empty/new\n~ link\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_tree_build() -> Result<()> {
+        let tree = MemoryTree::new("memory")
+            .file("./a//puppy", "puppy\n")
+            .symlink("a/link", "puppy")
+            .file("a/puppy", "kitty\n")
+            .file("a/link", "link\n")
+            .symlink("doggy", "a/puppy")
+            .dir("a")
+            .dir("a/b/c");
+
+        let names = |relative: &str| -> Result<Vec<_>> {
+            Ok(tree
+                .read_dir(Path::new(relative))?
+                .into_iter()
+                .map(|entry| entry.name().to_owned())
+                .collect())
+        };
+        assert_eq!(names("")?, vec!["a", "doggy"]);
+        assert_eq!(names("a")?, vec!["b", "link", "puppy"]);
+        assert_eq!(names("a/b")?, vec!["c"]);
+
+        // Replacing a file or symbolic link replaces its contents.
+        let read = |relative: &str| -> Result<String> {
+            let mut contents = String::new();
+            tree.open(Path::new(relative))?
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            Ok(contents)
+        };
+        assert_eq!(read("a/puppy")?, "kitty\n");
+        assert_eq!(read("a/link")?, "link\n");
+        assert_eq!(tree.contents.len(), 2);
+
+        let doggy = tree.stat(Path::new("doggy"))?;
+        assert_eq!(doggy.file_type(), FileType::Symlink);
+        assert_eq!(doggy.size(), "a/puppy".len() as u64);
+        assert_eq!(tree.read_link(Path::new("doggy"))?, Path::new("a/puppy"));
+        assert!(tree.read_link(Path::new("a/puppy")).is_err());
+        assert!(tree.stat(Path::new("missing")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't a relative path inside the tree")]
+    fn test_memory_tree_absolute() {
+        MemoryTree::new("memory").file("/puppy", "puppy\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't a relative path inside the tree")]
+    fn test_memory_tree_outside() {
+        MemoryTree::new("memory").dir("a/../../puppy");
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't a directory")]
+    fn test_memory_tree_file_parent() {
+        MemoryTree::new("memory")
+            .file("puppy", "puppy\n")
+            .file("puppy/doggy", "doggy\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "is already in the tree and isn't a directory")]
+    fn test_memory_tree_replace_with_dir() {
+        MemoryTree::new("memory")
+            .symlink("puppy", "doggy")
+            .dir("puppy");
+    }
+
+    #[test]
+    #[should_panic(expected = "is already a directory")]
+    fn test_memory_tree_replace_dir() {
+        MemoryTree::new("memory")
+            .dir("puppy")
+            .file("puppy", "puppy\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't in the tree")]
+    fn test_memory_tree_missing() {
+        MemoryTree::new("memory").mode("puppy", 0o644);
+    }
+}
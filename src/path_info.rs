@@ -1,24 +1,143 @@
 use std::fs::Metadata;
+use std::io::Read;
 use std::path::Path;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::SystemTime;
 
+use crate::FileType;
+use crate::ReadError;
+use crate::Result;
+use crate::base::Base;
+use crate::stat::Stat;
+use crate::tree::Tree;
+
+/// Information about one side of a [`crate::DiffEntry`].
 #[derive(Debug, Clone)]
 pub struct PathInfo<'a> {
-    pub(crate) metadata: Metadata,
-    pub(crate) base: &'a Path,
+    pub(crate) stat: Stat,
+    pub(crate) base: Base<'a>,
+    pub(crate) tree: Arc<dyn Tree>,
+    pub(crate) hash: OnceLock<blake3::Hash>,
 }
 
 impl<'a> PathInfo<'a> {
-    /// Get the metadata for this path.
-    pub fn metadata(&self) -> &Metadata {
-        &self.metadata
+    pub(crate) fn new(stat: Stat, base: Base<'a>, tree: Arc<dyn Tree>) -> Self {
+        Self {
+            stat,
+            base,
+            tree,
+            hash: OnceLock::new(),
+        }
+    }
+
+    /// Get the metadata for this path, as reported by its [`Tree`].
+    pub fn stat(&self) -> &Stat {
+        &self.stat
+    }
+
+    /// Get the filesystem metadata for this path.
+    ///
+    /// This is `None` unless the path is on the local filesystem.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.stat.metadata.as_ref()
+    }
+
+    /// The type of this path.
+    pub fn file_type(&self) -> FileType {
+        self.stat.file_type
+    }
+
+    /// The size of this path in bytes.
+    pub fn size(&self) -> u64 {
+        self.stat.size
+    }
+
+    /// The permission bits of this path, if known.
+    pub fn mode(&self) -> Option<u32> {
+        self.stat.mode
+    }
+
+    /// The user ID of this path's owner, if known.
+    pub fn uid(&self) -> Option<u32> {
+        self.stat.owner.map(|(uid, _)| uid)
+    }
+
+    /// The group ID of this path's owner, if known.
+    pub fn gid(&self) -> Option<u32> {
+        self.stat.owner.map(|(_, gid)| gid)
+    }
+
+    /// The modification time of this path, if known.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.stat.mtime
     }
 
     /// Get the comparison base for this path.
     ///
-    /// This is one of the paths being diffed.
+    /// This is one of the paths being diffed (e.g. a directory or a manifest file).
     ///
-    /// The [`DiffEntry::relative`] field can be joined to the `base` to recover the original path.
+    /// For directories, the [`crate::DiffEntry::relative`] field can be joined to the `base` to
+    /// recover the original path.
     pub fn base(&self) -> &Path {
-        self.base
+        &self.base
+    }
+
+    /// Get the [`blake3`] hash of this file, if it was computed while diffing.
+    ///
+    /// Files are only hashed when necessary, e.g. when they have the same length as the file
+    /// they're compared to.
+    pub fn hash(&self) -> Option<blake3::Hash> {
+        self.hash.get().copied()
+    }
+
+    /// Convert this into a [`PathInfo`] which owns its [`PathInfo::base`].
+    pub fn into_owned(self) -> PathInfo<'static> {
+        let base = self.base.clone().into_owned();
+        self.with_base(base)
+    }
+
+    pub(crate) fn with_base<'b>(self, base: Base<'b>) -> PathInfo<'b> {
+        PathInfo {
+            stat: self.stat,
+            base,
+            tree: self.tree,
+            hash: self.hash,
+        }
+    }
+
+    /// Hash the file at `relative`, or get the hash if it's already been computed.
+    pub(crate) fn hash_file(&self, relative: &Path) -> Result<blake3::Hash> {
+        match self.hash.get() {
+            Some(hash) => Ok(*hash),
+            None => {
+                let hash = self.tree.hash_file(relative, &self.stat)?;
+                Ok(*self.hash.get_or_init(|| hash))
+            }
+        }
+    }
+
+    /// Open the file at `relative` for reading from the tree this path is in.
+    ///
+    /// Unlike joining `relative` to [`PathInfo::base`], this works for any [`Tree`], including
+    /// archives and trees which aren't on the local filesystem. Returns `None` if the tree doesn't
+    /// record file contents (like a [`crate::Manifest`]).
+    pub fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        self.tree.open(relative)
+    }
+
+    /// Read the contents of the file at `relative`. See [`PathInfo::open`].
+    pub fn read(&self, relative: &Path) -> Result<Option<Vec<u8>>> {
+        let Some(mut reader) = self.open(relative)? else {
+            return Ok(None);
+        };
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .map_err(|inner| ReadError {
+                path: self.tree.root().join(relative),
+                inner,
+            })?;
+        Ok(Some(contents))
     }
 }
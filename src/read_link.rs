@@ -0,0 +1,14 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ReadLinkError;
+use crate::Result;
+
+pub(crate) fn read_link(path: impl AsRef<Path>) -> Result<PathBuf> {
+    let path = path.as_ref();
+    tracing::trace!("Reading link {path:?}");
+    Ok(std::fs::read_link(path).map_err(|inner| ReadLinkError {
+        path: path.to_owned(),
+        inner,
+    })?)
+}
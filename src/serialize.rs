@@ -0,0 +1,230 @@
+//! [`serde::Serialize`] implementations, enabled with the `serde` feature.
+
+use std::path::Path;
+
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeSeq;
+use serde::ser::SerializeStruct;
+
+use crate::Diff;
+use crate::DiffEntry;
+use crate::PathInfo;
+use crate::stat::unix_time;
+
+/// Serialized as `{"entries": [...], "errors": [...]}`, where errors are formatted as strings.
+impl<'a> Serialize for Diff<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Diff", 2)?;
+        state.serialize_field("entries", &Entries(self))?;
+        state.serialize_field("errors", &Errors(self))?;
+        state.end()
+    }
+}
+
+struct Entries<'d, 'a>(&'d Diff<'a>);
+
+impl<'d, 'a> Serialize for Entries<'d, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.entries.len()))?;
+        for entry in &self.0.entries {
+            seq.serialize_element(entry)?;
+        }
+        seq.end()
+    }
+}
+
+struct Errors<'d, 'a>(&'d Diff<'a>);
+
+impl<'d, 'a> Serialize for Errors<'d, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.errors.iter().map(ToString::to_string))
+    }
+}
+
+/// Paths which aren't valid UTF-8 are serialized lossily.
+impl<'a> Serialize for DiffEntry<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("DiffEntry", 8)?;
+        state.serialize_field("path", &self.relative.to_string_lossy())?;
+        state.serialize_field("tag", &self.tag)?;
+        state.serialize_field(
+            "renamed_from",
+            &self.renamed_from.as_deref().map(Path::to_string_lossy),
+        )?;
+        state.serialize_field("changes", &self.changes)?;
+        state.serialize_field("content_diff", &self.content_diff)?;
+        state.serialize_field("assumed_equal", &self.assumed_equal)?;
+        state.serialize_field("old", &self.deleted)?;
+        state.serialize_field("new", &self.inserted)?;
+        state.end()
+    }
+}
+
+impl<'a> Serialize for PathInfo<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mtime = self.stat.mtime.map(unix_time);
+        let mut state = serializer.serialize_struct("PathInfo", 9)?;
+        state.serialize_field("base", &self.base.to_string_lossy())?;
+        state.serialize_field("file_type", self.stat.file_type.as_str())?;
+        state.serialize_field("size", &self.stat.size)?;
+        state.serialize_field("mode", &self.mode())?;
+        state.serialize_field("uid", &self.uid())?;
+        state.serialize_field("gid", &self.gid())?;
+        state.serialize_field("mtime", &mtime.map(|(secs, _)| secs))?;
+        state.serialize_field("mtime_nsec", &mtime.map(|(_, nsecs)| nsecs))?;
+        state.serialize_field("hash", &self.hash().map(|hash| hash.to_hex().to_string()))?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    use serde_json::json;
+    use testlib::TempTree;
+
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::MemoryTree;
+    use crate::Result;
+
+    #[test]
+    fn test_serialize() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.file("puppy", "puppy")
+            .unwrap()
+            .mode("puppy", 0o644)
+            .unwrap()
+            .file("doggy", "doggy")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.file("puppy", "kitty")
+            .unwrap()
+            .mode("puppy", 0o644)
+            .unwrap();
+
+        let diff = Diff::new(old.as_ref(), new.as_ref())?;
+        let json = serde_json::to_value(&diff).unwrap();
+
+        assert_eq!(json["errors"], json!([]));
+
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0]["path"], "doggy");
+        assert_eq!(entries[0]["tag"], "delete");
+        assert_eq!(entries[0]["old"]["file_type"], "file");
+        assert_eq!(entries[0]["old"]["size"], 5);
+        assert_eq!(entries[0]["old"]["hash"], json!(null));
+        assert_eq!(entries[0]["new"], json!(null));
+
+        assert_eq!(entries[1]["path"], "puppy");
+        assert_eq!(entries[1]["tag"], "replace");
+        assert_eq!(
+            entries[1]["changes"],
+            json!({"content": true, "mode": false, "owner": false, "mtime": false})
+        );
+        assert_eq!(entries[1]["old"]["mode"], 0o644);
+        assert_eq!(
+            entries[1]["old"]["hash"],
+            blake3::hash(b"puppy").to_hex().as_str()
+        );
+        assert_eq!(
+            entries[1]["new"]["hash"],
+            blake3::hash(b"kitty").to_hex().as_str()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_non_utf8() -> Result<()> {
+        let name = |bytes: &[u8]| OsStr::from_bytes(bytes).to_owned();
+        let old = MemoryTree::new(name(b"/old\xff")).file(name(b"pup\xffpy"), "puppy");
+        let new = MemoryTree::new("/new")
+            .file("doggy", "puppy")
+            .file(name(b"kit\xffty"), "kitty");
+
+        let diff =
+            Diff::with_sources(old.into(), new.into(), DiffOpts::new().detect_renames(true))?;
+        let json = serde_json::to_value(&diff).unwrap();
+
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["path"], "doggy");
+        assert_eq!(entries[0]["tag"], "rename");
+        assert_eq!(entries[0]["renamed_from"], "pup\u{fffd}py");
+        assert_eq!(entries[0]["old"]["base"], "/old\u{fffd}");
+        assert_eq!(entries[1]["path"], "kit\u{fffd}ty");
+        assert_eq!(entries[1]["tag"], "insert");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_write_ndjson() -> Result<()> {
+        let mut old = TempTree::new().unwrap();
+        old.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy")
+            .unwrap()
+            .file("doggy", "doggy")
+            .unwrap();
+
+        let mut new = TempTree::new().unwrap();
+        new.dir("a").unwrap().file("a/puppy", "kitty").unwrap();
+
+        let mut output = Vec::new();
+        let errors =
+            Diff::write_ndjson(old.as_ref(), new.as_ref(), Default::default(), &mut output)?;
+        assert!(errors.is_empty());
+
+        let entries = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0]["path"], "a/puppy");
+        assert_eq!(entries[0]["tag"], "replace");
+        assert_eq!(entries[1]["path"], "a");
+        assert_eq!(entries[1]["tag"], "replace");
+        assert_eq!(entries[1]["old"]["file_type"], "dir");
+        assert_eq!(entries[2]["path"], "doggy");
+        assert_eq!(entries[2]["tag"], "delete");
+
+        Ok(())
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_write_ndjson_non_utf8() -> Result<()> {
+        let old = MemoryTree::new("/old").file("puppy", "puppy");
+        let new = MemoryTree::new("/new")
+            .file("puppy", "kitty")
+            .file(OsStr::from_bytes(b"dog\xffgy"), "doggy");
+
+        let mut output = Vec::new();
+        let errors =
+            Diff::write_ndjson_sources(old.into(), new.into(), Default::default(), &mut output)?;
+        assert!(errors.is_empty());
+
+        let entries = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["path"], "dog\u{fffd}gy");
+        assert_eq!(entries[0]["tag"], "insert");
+        assert_eq!(entries[1]["path"], "puppy");
+        assert_eq!(entries[1]["tag"], "replace");
+
+        Ok(())
+    }
+}
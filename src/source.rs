@@ -0,0 +1,156 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::DiffOpts;
+use crate::Manifest;
+use crate::MemoryTree;
+#[cfg(any(feature = "tar", feature = "zip", feature = "git"))]
+use crate::Result;
+use crate::base::Base;
+#[cfg(feature = "git")]
+use crate::git_tree::GitTree;
+use crate::local_tree::LocalTree;
+#[cfg(feature = "tar")]
+use crate::tar_tree::TarTree;
+use crate::tree::Tree;
+#[cfg(feature = "zip")]
+use crate::zip_tree::ZipTree;
+
+/// One side of a diff: a directory on the local filesystem, a [`Manifest`] of one, an archive,
+/// a tree in a git repository, or a custom [`Tree`].
+///
+/// A `&Path` can be converted into a [`Source::dir`], a [`Manifest`] can be converted into a
+/// [`Source::manifest`], and a [`MemoryTree`] can be converted into a [`Source::tree`].
+///
+/// Attributes which a source doesn't record (like owners in a manifest) are never considered
+/// changed. Symbolic links are only followed in directories on the local filesystem; see
+/// [`DiffOpts::follow_links`].
+#[derive(Debug, Clone)]
+pub struct Source<'a> {
+    kind: SourceKind<'a>,
+}
+
+#[derive(Debug, Clone)]
+enum SourceKind<'a> {
+    Dir(&'a Path),
+    Tree(Arc<dyn Tree>),
+}
+
+impl<'a> Source<'a> {
+    /// A directory on the local filesystem.
+    pub fn dir(path: &'a Path) -> Self {
+        Self {
+            kind: SourceKind::Dir(path),
+        }
+    }
+
+    /// A [`Manifest`] recording the contents of a directory.
+    ///
+    /// Manifests don't record file contents, so files are compared by their recorded hashes and
+    /// [`DiffOpts::text_diff`] doesn't produce diffs for them. Custom [`DiffOpts::comparator`]s
+    /// which read files directly won't work with manifests. Manifests also don't record owners or
+    /// modification times.
+    pub fn manifest(manifest: Manifest) -> Self {
+        Self {
+            kind: SourceKind::Tree(Arc::new(manifest)),
+        }
+    }
+
+    /// A tar archive, which may be compressed with gzip or zstd.
+    ///
+    /// The archive is read once up front to list and hash its members. Reading a member's
+    /// contents (e.g. for [`DiffOpts::text_diff`]) reads the archive again up to that member.
+    ///
+    /// Directories which are only implied by the paths of their contents have no mode, owner, or
+    /// modification time. Hard links are treated as copies of the files they link to.
+    #[cfg(feature = "tar")]
+    pub fn tar(path: &Path) -> Result<Self> {
+        Ok(Self {
+            kind: SourceKind::Tree(Arc::new(TarTree::open(path)?)),
+        })
+    }
+
+    /// A zip archive, like a `.jar` or `.whl` file.
+    ///
+    /// The archive's central directory is read up front to list its members, and members are
+    /// decompressed as they're needed. Files with different CRC-32 checksums are known to differ
+    /// without decompressing them when comparing two zip archives.
+    ///
+    /// Modes are read from the members' external attributes, and are only recorded by archives
+    /// created on Unix systems. Zip archives record modification times without a time zone, so
+    /// they're ignored, and don't record owners. Directories which are only implied by the paths
+    /// of their contents have no mode.
+    #[cfg(feature = "zip")]
+    pub fn zip(path: &Path) -> Result<Self> {
+        Ok(Self {
+            kind: SourceKind::Tree(Arc::new(ZipTree::open(path)?)),
+        })
+    }
+
+    /// A tree in the local git repository at `repo`, like a commit.
+    ///
+    /// `rev` is anything `git rev-parse` understands which names a tree, like `HEAD`, `v1.0.0`,
+    /// or `main:src`. Only the repository's object database is read, so the working directory
+    /// and index don't matter. [`PathInfo::base`](crate::PathInfo::base) is `{repo}@{rev}`.
+    ///
+    /// Files in two git trees are compared by their blob object IDs without reading them. Blobs
+    /// are compared to other files as they're stored, without applying git's filters (like line
+    /// ending conversion). Git only records whether files are executable, so files have mode
+    /// `0o644` or `0o755`, and directories and symbolic links have no mode. Submodules are
+    /// [`crate::FileType::Other`].
+    ///
+    /// To diff against a working directory, use [`Source::dir`] and [`DiffOpts::exclude`] the
+    /// `.git` directory.
+    #[cfg(feature = "git")]
+    pub fn git(repo: &Path, rev: &str) -> Result<Self> {
+        Ok(Self {
+            kind: SourceKind::Tree(Arc::new(GitTree::open(repo, rev)?)),
+        })
+    }
+
+    /// A custom [`Tree`], like a virtual filesystem.
+    ///
+    /// A custom tree which wants symbolic links followed should report their targets' metadata
+    /// instead, since [`DiffOpts::follow_links`] doesn't apply to it.
+    pub fn tree(tree: impl Tree + 'static) -> Self {
+        Self {
+            kind: SourceKind::Tree(Arc::new(tree)),
+        }
+    }
+
+    pub(crate) fn open(&self, opts: &DiffOpts) -> Arc<dyn Tree> {
+        match &self.kind {
+            SourceKind::Dir(path) => Arc::new(LocalTree::new(
+                path,
+                opts.follow_links,
+                opts.hash_cache.clone(),
+            )),
+            SourceKind::Tree(tree) => tree.clone(),
+        }
+    }
+
+    pub(crate) fn base(&self) -> Base<'a> {
+        match &self.kind {
+            SourceKind::Dir(path) => Base::Borrowed(path),
+            SourceKind::Tree(tree) => Base::Shared(tree.root().into()),
+        }
+    }
+}
+
+impl<'a> From<&'a Path> for Source<'a> {
+    fn from(path: &'a Path) -> Self {
+        Self::dir(path)
+    }
+}
+
+impl From<Manifest> for Source<'_> {
+    fn from(manifest: Manifest) -> Self {
+        Self::manifest(manifest)
+    }
+}
+
+impl From<MemoryTree> for Source<'_> {
+    fn from(tree: MemoryTree) -> Self {
+        Self::tree(tree)
+    }
+}
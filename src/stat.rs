@@ -0,0 +1,172 @@
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+#[cfg(feature = "serde")]
+use std::time::UNIX_EPOCH;
+
+use crate::FileType;
+
+/// Metadata for a path in a [`crate::Tree`].
+///
+/// Attributes which a tree doesn't record (like the owner of a path in a manifest) are `None`,
+/// and are never considered changed.
+#[derive(Debug, Clone)]
+pub struct Stat {
+    pub(crate) file_type: FileType,
+    pub(crate) size: u64,
+    /// The permission bits.
+    pub(crate) mode: Option<u32>,
+    /// The user and group IDs.
+    pub(crate) owner: Option<(u32, u32)>,
+    pub(crate) mtime: Option<SystemTime>,
+    /// The device and inode numbers, which identify a path on the local filesystem.
+    pub(crate) id: Option<(u64, u64)>,
+    /// A checksum of a file's contents, for trees which record one.
+    pub(crate) checksum: Option<Checksum>,
+    /// The hash of a file's contents, for trees which record one.
+    pub(crate) hash: Option<blake3::Hash>,
+    /// The metadata this was read from, for paths on the local filesystem.
+    pub(crate) metadata: Option<Metadata>,
+}
+
+impl Stat {
+    pub(crate) fn from_metadata(metadata: Metadata) -> Self {
+        Self {
+            file_type: FileType::from_std(metadata.file_type()),
+            size: metadata.len(),
+            mode: Some(metadata.mode() & 0o7777),
+            owner: Some((metadata.uid(), metadata.gid())),
+            mtime: metadata.modified().ok(),
+            id: Some((metadata.dev(), metadata.ino())),
+            checksum: None,
+            hash: None,
+            metadata: Some(metadata),
+        }
+    }
+
+    /// Metadata for a path with the given type and size, and no other attributes.
+    ///
+    /// The size of a directory is ignored, and the size of a symbolic link is the length of its
+    /// target.
+    pub fn new(file_type: FileType, size: u64) -> Self {
+        Self {
+            file_type,
+            size,
+            mode: None,
+            owner: None,
+            mtime: None,
+            id: None,
+            checksum: None,
+            hash: None,
+            metadata: None,
+        }
+    }
+
+    /// Set the permission bits, like `0o644`.
+    pub fn with_mode(self, mode: u32) -> Self {
+        Self {
+            mode: Some(mode & 0o7777),
+            ..self
+        }
+    }
+
+    /// Set the user and group IDs of the owner.
+    pub fn with_owner(self, uid: u32, gid: u32) -> Self {
+        Self {
+            owner: Some((uid, gid)),
+            ..self
+        }
+    }
+
+    /// Set the modification time.
+    pub fn with_mtime(self, mtime: SystemTime) -> Self {
+        Self {
+            mtime: Some(mtime),
+            ..self
+        }
+    }
+
+    /// Set the [`blake3`] hash of the file's contents.
+    ///
+    /// Files with hashes are compared without reading their contents.
+    pub fn with_hash(self, hash: blake3::Hash) -> Self {
+        Self {
+            hash: Some(hash),
+            ..self
+        }
+    }
+
+    /// The type of the path.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// The size of the path in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The permission bits, if the tree records them.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// The user ID of the owner, if the tree records it.
+    pub fn uid(&self) -> Option<u32> {
+        self.owner.map(|(uid, _)| uid)
+    }
+
+    /// The group ID of the owner, if the tree records it.
+    pub fn gid(&self) -> Option<u32> {
+        self.owner.map(|(_, gid)| gid)
+    }
+
+    /// The modification time, if the tree records it.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.mtime
+    }
+
+    /// The hash of the file's contents, if the tree records it.
+    pub fn hash(&self) -> Option<blake3::Hash> {
+        self.hash
+    }
+
+    pub(crate) fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+
+    pub(crate) fn is_dir(&self) -> bool {
+        self.file_type == FileType::Dir
+    }
+
+    pub(crate) fn is_symlink(&self) -> bool {
+        self.file_type == FileType::Symlink
+    }
+}
+
+/// A checksum of a file's contents recorded by a tree, which can be compared without reading
+/// the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Checksum {
+    /// A CRC-32 checksum. Different files may have the same checksum.
+    #[cfg_attr(not(feature = "zip"), allow(dead_code))]
+    Crc32(u32),
+    /// A git blob object ID, which identifies the contents exactly.
+    #[cfg_attr(not(feature = "git"), allow(dead_code))]
+    GitOid(Vec<u8>),
+}
+
+/// Split a time into seconds and nanoseconds since the Unix epoch, like `stat(2)`.
+#[cfg(feature = "serde")]
+pub(crate) fn unix_time(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() as i64, duration.subsec_nanos()),
+        Err(err) => {
+            let duration = err.duration();
+            match duration.subsec_nanos() {
+                0 => (-(duration.as_secs() as i64), 0),
+                nanos => (-(duration.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
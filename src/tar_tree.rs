@@ -0,0 +1,406 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
+
+use tar::Archive;
+use tar::EntryType;
+
+use crate::ArchiveError;
+use crate::FileType;
+use crate::Result;
+use crate::archive_index::ArchiveEntry;
+use crate::archive_index::ArchiveIndex;
+use crate::archive_index::normalize;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// A tar archive, optionally compressed with gzip or zstd.
+///
+/// Members are listed and hashed when the archive is opened. Their contents aren't kept in
+/// memory, so reading a member reads the archive again.
+#[derive(Debug)]
+pub(crate) struct TarTree {
+    index: ArchiveIndex,
+}
+
+impl TarTree {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let mut tree = Self {
+            index: ArchiveIndex::new(path),
+        };
+
+        let mut archive = tree.archive()?;
+        let members = archive.entries().map_err(|inner| tree.error(inner))?;
+        for (index, member) in members.enumerate() {
+            let mut member = member.map_err(|inner| tree.error(inner))?;
+            let member_path = member.path().map_err(|inner| tree.error(inner))?;
+            let Some(relative) = normalize(&member_path) else {
+                tracing::warn!("Skipping archive member with unsafe path {member_path:?}");
+                continue;
+            };
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+
+            let header = member.header();
+            let mut stat = Stat {
+                mode: header.mode().ok().map(|mode| mode & 0o7777),
+                owner: header
+                    .uid()
+                    .ok()
+                    .zip(header.gid().ok())
+                    .map(|(uid, gid)| (uid as u32, gid as u32)),
+                mtime: header
+                    .mtime()
+                    .ok()
+                    .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+                ..Stat::new(FileType::Other, member.size())
+            };
+
+            let entry = match header.entry_type() {
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    stat.file_type = FileType::File;
+                    let mut hasher = blake3::Hasher::new();
+                    hasher
+                        .update_reader(&mut member)
+                        .map_err(|inner| tree.error(inner))?;
+                    ArchiveEntry {
+                        stat,
+                        index: Some(index),
+                        hash: Some(hasher.finalize()),
+                        target: None,
+                    }
+                }
+                EntryType::Directory => {
+                    stat.file_type = FileType::Dir;
+                    stat.size = 0;
+                    ArchiveEntry::new(stat)
+                }
+                EntryType::Symlink => {
+                    stat.file_type = FileType::Symlink;
+                    let target = member
+                        .link_name()
+                        .map_err(|inner| tree.error(inner))?
+                        .unwrap_or_default()
+                        .into_owned();
+                    // Like `lstat(2)`, the size of a symbolic link is the length of its target.
+                    stat.size = target.as_os_str().len() as u64;
+                    ArchiveEntry {
+                        target: Some(target),
+                        ..ArchiveEntry::new(stat)
+                    }
+                }
+                EntryType::Link => {
+                    let target = member
+                        .link_name()
+                        .map_err(|inner| tree.error(inner))?
+                        .and_then(|target| normalize(&target));
+                    match target.and_then(|target| tree.index.get(&target)) {
+                        Some(entry) if entry.stat.is_file() => entry.clone(),
+                        _ => {
+                            tracing::warn!("Skipping hard link {relative:?} to a missing file");
+                            continue;
+                        }
+                    }
+                }
+                EntryType::XGlobalHeader
+                | EntryType::XHeader
+                | EntryType::GNULongName
+                | EntryType::GNULongLink => continue,
+                _ => ArchiveEntry::new(stat),
+            };
+
+            tree.index.insert(relative, entry);
+        }
+
+        Ok(tree)
+    }
+
+    /// Open the archive for reading from the start, detecting compression from its first bytes.
+    fn archive(&self) -> Result<Archive<Box<dyn Read>>> {
+        let mut file = BufReader::new(
+            std::fs::File::open(self.index.path()).map_err(|inner| self.error(inner))?,
+        );
+        let magic = file.fill_buf().map_err(|inner| self.error(inner))?;
+        let reader: Box<dyn Read> = if magic.starts_with(&[0x1f, 0x8b]) {
+            Box::new(flate2::bufread::MultiGzDecoder::new(file))
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Box::new(zstd::Decoder::with_buffer(file).map_err(|inner| self.error(inner))?)
+        } else {
+            Box::new(file)
+        };
+        Ok(Archive::new(reader))
+    }
+
+    fn error(&self, inner: std::io::Error) -> ArchiveError {
+        ArchiveError {
+            path: self.index.path().to_owned(),
+            inner,
+        }
+    }
+
+    /// Read the contents of the member at `index`.
+    fn read_member(&self, index: usize) -> Result<Vec<u8>> {
+        let mut archive = self.archive()?;
+        let mut members = archive.entries().map_err(|inner| self.error(inner))?;
+        let mut member = members
+            .nth(index)
+            .ok_or_else(|| self.error(std::io::ErrorKind::UnexpectedEof.into()))?
+            .map_err(|inner| self.error(inner))?;
+        let mut contents = Vec::new();
+        member
+            .read_to_end(&mut contents)
+            .map_err(|inner| self.error(inner))?;
+        Ok(contents)
+    }
+}
+
+impl Tree for TarTree {
+    fn root(&self) -> &Path {
+        self.index.path()
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        Ok(self.index.read_dir(relative))
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        self.index.stat(relative)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        self.index.read_link(relative)
+    }
+
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        let index = self.index.member(relative)?;
+        tracing::trace!("Reading {relative:?} from {:?}", self.index.path());
+        Ok(Some(Box::new(Cursor::new(self.read_member(index)?))))
+    }
+
+    fn hash_file(&self, relative: &Path, _stat: &Stat) -> Result<blake3::Hash> {
+        match self.index.hash(relative)? {
+            Some(hash) => Ok(hash),
+            None => Ok(blake3::hash(
+                &self.read_member(self.index.member(relative)?)?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::DiffTag;
+    use crate::Error;
+    use crate::Source;
+
+    #[test]
+    fn test_tar() -> Result<()> {
+        fn build<W: std::io::Write>(dir: &Path, writer: W) -> W {
+            let mut builder = tar::Builder::new(writer);
+            builder.follow_symlinks(false);
+            builder.append_dir_all(".", dir).unwrap();
+            builder.into_inner().unwrap()
+        }
+
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap();
+
+        let archives = TempTree::new().unwrap();
+        let archives = AsRef::<Path>::as_ref(&archives);
+        let tar = archives.join("tree.tar");
+        build(tree.as_ref(), std::fs::File::create(&tar).unwrap());
+        let tar_gz = archives.join("tree.tar.gz");
+        build(
+            tree.as_ref(),
+            flate2::write::GzEncoder::new(
+                std::fs::File::create(&tar_gz).unwrap(),
+                Default::default(),
+            ),
+        )
+        .finish()
+        .unwrap();
+        let tar_zst = archives.join("tree.tar.zst");
+        build(
+            tree.as_ref(),
+            zstd::Encoder::new(std::fs::File::create(&tar_zst).unwrap(), 0).unwrap(),
+        )
+        .finish()
+        .unwrap();
+
+        tree.file("doggy", "kitty\n").unwrap();
+        let opts = DiffOpts::new().follow_links(false).text_diff(true);
+        for archive in [&tar, &tar_gz, &tar_zst] {
+            let diff = Diff::with_sources(
+                Source::tar(archive)?,
+                Source::dir(tree.as_ref()),
+                opts.clone(),
+            )?;
+            assert_eq!(
+                diff.to_string(),
+                indoc::indoc! {"
+                    ~ doggy
+                    @@ -1 +1 @@
+                    -doggy
+                    +kitty
+                "},
+                "{archive:?}"
+            );
+            let puppy = diff.get(Path::new("a/puppy")).unwrap();
+            assert_eq!(puppy.tag(), DiffTag::Equal);
+            assert_eq!(puppy.deleted().unwrap().base(), archive.as_path());
+            let link = diff.get(Path::new("link")).unwrap();
+            assert_eq!(link.tag(), DiffTag::Equal);
+            assert_eq!(link.deleted().unwrap().size(), "a/puppy".len() as u64);
+        }
+
+        // Directories don't need to be listed in the archive.
+        let mut builder = tar::Builder::new(std::fs::File::create(&tar).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "a/puppy", b"puppy\n".as_slice())
+            .unwrap();
+        builder.finish().unwrap();
+        let diff =
+            Diff::with_sources(Source::tar(&tar)?, Source::dir(tree.as_ref()), opts.clone())?;
+        assert_eq!(diff.to_string(), "+ doggy\n+ link\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_links() -> Result<()> {
+        let archives = TempTree::new().unwrap();
+        let tar = archives.child("links.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&tar).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "puppy", b"puppy\n".as_slice())
+            .unwrap();
+        for (path, target) in [("copy", "puppy"), ("missing", "nowhere")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(EntryType::Link);
+            header.set_size(0);
+            builder.append_link(&mut header, path, target).unwrap();
+        }
+        builder.finish().unwrap();
+
+        // Hard links are copies of the files they link to, and are skipped if they're broken.
+        let tree = TarTree::open(&tar)?;
+        assert_eq!(
+            tree.read_dir(Path::new(""))?
+                .iter()
+                .map(|entry| entry.name().to_owned())
+                .collect::<Vec<_>>(),
+            vec!["copy", "puppy"]
+        );
+        let mut contents = String::new();
+        tree.open(Path::new("copy"))?
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "puppy\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_replaced() -> Result<()> {
+        let archives = TempTree::new().unwrap();
+        let tar = archives.child("replaced.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&tar).unwrap());
+        for path in ["a", "a/b", "c/d/e", "c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(6);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, path, b"puppy\n".as_slice())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        // Like when extracting the archive, later members replace earlier ones in their way.
+        let tree = TarTree::open(&tar)?;
+        let names = |relative: &str| -> Result<Vec<OsString>> {
+            Ok(tree
+                .read_dir(Path::new(relative))?
+                .iter()
+                .map(|entry| entry.name().to_owned())
+                .collect())
+        };
+        assert_eq!(names("")?, vec!["a", "c"]);
+        assert!(tree.stat(Path::new("a"))?.is_dir());
+        assert_eq!(names("a")?, vec!["b"]);
+        assert!(tree.stat(Path::new("c"))?.is_file());
+        assert!(names("c")?.is_empty());
+        assert!(tree.stat(Path::new("c/d")).is_err());
+        assert!(tree.stat(Path::new("c/d/e")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_corrupt() -> Result<()> {
+        let mut tree = TempTree::new().unwrap();
+        tree.file("puppy", "puppy\n".repeat(1000)).unwrap();
+
+        let archives = TempTree::new().unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder
+            .append_dir_all(".", AsRef::<Path>::as_ref(&tree))
+            .unwrap();
+        let valid = builder.into_inner().unwrap();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        std::io::Write::write_all(&mut gzip, &valid).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        for (name, contents) in [
+            ("truncated.tar", &valid[..1024]),
+            ("checksum.tar", &[b"puppy".as_slice(), &valid[5..]].concat()),
+            ("truncated.tar.gz", &gzip[..gzip.len() / 2]),
+            ("garbage.tar.gz", &[0x1f, 0x8b, 0, 0, 0, 0]),
+            ("garbage.tar.zst", &[0x28, 0xb5, 0x2f, 0xfd, 0, 0]),
+        ] {
+            let path = archives.child(name);
+            std::fs::write(&path, contents).unwrap();
+            assert!(
+                matches!(Source::tar(&path), Err(Error::Archive(_))),
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            Source::tar(&archives.child("missing.tar")),
+            Err(Error::Archive(_))
+        ));
+
+        Ok(())
+    }
+}
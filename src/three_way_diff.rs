@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::ops::Deref;
+use std::path::Path;
+use std::path::PathBuf;
+
+use iddqd::IdOrdMap;
+
+use crate::Diff;
+use crate::DiffEntry;
+use crate::DiffOpts;
+use crate::DiffTag;
+use crate::DisplayDiffOpts;
+use crate::Error;
+use crate::Result;
+use crate::Source;
+use crate::ThreeWayEntry;
+use crate::ThreeWayTag;
+use crate::display_three_way_diff::DisplayThreeWayDiff;
+use crate::three_way_entry::is_changed;
+
+/// A diff of two trees ('ours' and 'theirs') which were derived from a common base, like the two
+/// sides of a merge.
+///
+/// Each path in any of the trees is classified with a [`ThreeWayTag`], depending on whether it
+/// changed from the base in 'ours', 'theirs', or both. Paths which changed in both are compared
+/// between 'ours' and 'theirs' to tell whether they changed the same way.
+///
+/// Unlike [`Diff`], changes to a directory's contents don't count as changes to the directory
+/// itself, so a directory whose contents changed in different ways in 'ours' and 'theirs' doesn't
+/// conflict unless its metadata or type changed too. Deleting a directory counts as deleting
+/// everything in it, so it (and everything between it and the changes) conflicts with changes to
+/// its contents in the other side. A directory
+/// which was added in both sides conflicts unless its contents are the same in both.
+#[derive(Debug)]
+pub struct ThreeWayDiff<'a> {
+    pub(crate) entries: IdOrdMap<ThreeWayEntry<'a>>,
+    errors: Vec<Error>,
+}
+
+impl<'a> Deref for ThreeWayDiff<'a> {
+    type Target = IdOrdMap<ThreeWayEntry<'a>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl<'a> IntoIterator for ThreeWayDiff<'a> {
+    type Item = ThreeWayEntry<'a>;
+
+    type IntoIter = iddqd::id_ord_map::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ThreeWayDiff<'a> {
+    type Item = &'a ThreeWayEntry<'a>;
+
+    type IntoIter = iddqd::id_ord_map::Iter<'a, ThreeWayEntry<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&self.entries).into_iter()
+    }
+}
+
+impl<'a> ThreeWayDiff<'a> {
+    /// Diff two directory trees derived from the common `base` directory tree.
+    pub fn new(base: &'a Path, ours: &'a Path, theirs: &'a Path) -> Result<Self> {
+        Self::with_opts(base, ours, theirs, Default::default())
+    }
+
+    /// Diff two directory trees derived from the common `base` directory tree with the given
+    /// options.
+    pub fn with_opts(
+        base: &'a Path,
+        ours: &'a Path,
+        theirs: &'a Path,
+        opts: DiffOpts,
+    ) -> Result<Self> {
+        Self::with_sources(base.into(), ours.into(), theirs.into(), opts)
+    }
+
+    /// Diff two [`Source`]s derived from the common `base` [`Source`] with the given options.
+    ///
+    /// This computes three [`Diff`]s: from the base to each side, and between the sides. Renames
+    /// aren't detected, so [`DiffOpts::detect_renames`] is ignored.
+    pub fn with_sources(
+        base: Source<'a>,
+        ours: Source<'a>,
+        theirs: Source<'a>,
+        opts: DiffOpts,
+    ) -> Result<Self> {
+        let opts = opts.detect_renames(false);
+        let ours_diff = Diff::with_sources(base.clone(), ours.clone(), opts.clone())?;
+        let theirs_diff = Diff::with_sources(base, theirs.clone(), opts.clone())?;
+        // Only used to tell whether paths changed the same way.
+        let sides = Diff::with_sources(ours, theirs, opts.text_diff(false))?;
+
+        let ours_deleted = deleted_dirs(&ours_diff);
+        let theirs_deleted = deleted_dirs(&theirs_diff);
+
+        let mut paths: BTreeMap<PathBuf, (Option<DiffEntry<'a>>, Option<DiffEntry<'a>>)> =
+            BTreeMap::new();
+        for entry in ours_diff.entries {
+            let relative = entry.relative.clone();
+            paths.entry(relative).or_default().0 = Some(entry);
+        }
+        for entry in theirs_diff.entries {
+            let relative = entry.relative.clone();
+            paths.entry(relative).or_default().1 = Some(entry);
+        }
+        let mut errors = ours_diff.errors;
+        errors.extend(theirs_diff.errors);
+        errors.extend(sides.errors);
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for (relative, (ours, theirs)) in paths {
+            // Paths in a deleted directory have no entries of their own, so they only count as
+            // changed if the other side changed them too.
+            let ours_covered = ours.is_none() && is_covered(&ours_deleted, &relative);
+            let theirs_covered = theirs.is_none() && is_covered(&theirs_deleted, &relative);
+            let ours_own = ours.as_ref().is_some_and(is_changed);
+            let theirs_own = theirs.as_ref().is_some_and(is_changed);
+            let ours_changed = ours_own || (ours_covered && theirs_own);
+            let theirs_changed = theirs_own || (theirs_covered && ours_own);
+            let conflict = match (ours_covered, theirs_covered) {
+                (true, true) => false,
+                // The path was deleted from one side, so the sides only agree if it was deleted
+                // from the other too.
+                (true, false) => theirs
+                    .as_ref()
+                    .is_some_and(|entry| entry.tag != DiffTag::Delete),
+                (false, true) => ours
+                    .as_ref()
+                    .is_some_and(|entry| entry.tag != DiffTag::Delete),
+                (false, false) => match sides.entries.get(relative.as_path()) {
+                    // A directory which was added in either side isn't diffed against the base,
+                    // so its contents must be compared too.
+                    Some(entry)
+                        if entry.is_dir()
+                            && (is_added(ours.as_ref()) || is_added(theirs.as_ref())) =>
+                    {
+                        entry.tag != DiffTag::Equal
+                    }
+                    Some(entry) => is_changed(entry),
+                    // Paths which aren't in either side were deleted from both.
+                    None => false,
+                },
+            };
+            let tag = match (ours_changed, theirs_changed) {
+                (false, false) => ThreeWayTag::Unchanged,
+                (true, false) => ThreeWayTag::Ours,
+                (false, true) => ThreeWayTag::Theirs,
+                (true, true) if conflict => ThreeWayTag::Conflict,
+                (true, true) => ThreeWayTag::Both,
+            };
+            entries.push(ThreeWayEntry {
+                relative,
+                tag,
+                ours,
+                theirs,
+            });
+        }
+
+        // A deleted directory conflicts with conflicting changes to its contents, and so do the
+        // directories between them.
+        let conflicting_dirs = entries
+            .iter()
+            .filter(|entry| entry.tag == ThreeWayTag::Conflict)
+            .flat_map(|entry| entry.relative.ancestors().skip(1))
+            .filter(|dir| is_deleted(&ours_deleted, dir) || is_deleted(&theirs_deleted, dir))
+            .map(Path::to_owned)
+            .collect::<HashSet<_>>();
+        for entry in &mut entries {
+            if conflicting_dirs.contains(&entry.relative) {
+                entry.tag = ThreeWayTag::Conflict;
+            }
+        }
+
+        Ok(Self {
+            entries: entries.into_iter().collect(),
+            errors,
+        })
+    }
+
+    /// Errors encountered while diffing individual paths.
+    ///
+    /// This is always empty unless [`DiffOpts::tolerate_errors`] is enabled. Errors reading the
+    /// base may be reported twice.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// [`Display`] this diff with the given options.
+    ///
+    /// Each changed path is displayed with two markers, for how it changed in 'ours' and in
+    /// 'theirs', like `~+ path` (see [`Diff`]'s display format). Conflicts are marked with
+    /// `(conflict)`. Text diffs aren't displayed, but are available from
+    /// [`ThreeWayEntry::ours`] and [`ThreeWayEntry::theirs`].
+    pub fn display(&'a self, opts: DisplayDiffOpts) -> impl Display + 'a {
+        DisplayThreeWayDiff { diff: self, opts }
+    }
+}
+
+/// The directories which were deleted in `diff`.
+fn deleted_dirs(diff: &Diff<'_>) -> HashSet<PathBuf> {
+    diff.iter()
+        .filter(|entry| entry.tag == DiffTag::Delete && entry.is_dir())
+        .map(|entry| entry.relative.clone())
+        .collect()
+}
+
+/// Whether one of the parents of `relative` is in `deleted`.
+fn is_covered(deleted: &HashSet<PathBuf>, relative: &Path) -> bool {
+    relative
+        .ancestors()
+        .skip(1)
+        .any(|ancestor| deleted.contains(ancestor))
+}
+
+/// Whether `relative` or one of its parents is in `deleted`.
+fn is_deleted(deleted: &HashSet<PathBuf>, relative: &Path) -> bool {
+    deleted.contains(relative) || is_covered(deleted, relative)
+}
+
+/// Whether a path was added, or replaced with something else, rather than modified.
+fn is_added(entry: Option<&DiffEntry<'_>>) -> bool {
+    entry.is_some_and(|entry| matches!(entry.tag, DiffTag::Insert | DiffTag::TypeChange))
+}
+
+/// Display the diff with default options (no ANSI colors).
+impl<'a> Display for ThreeWayDiff<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(Default::default()).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::MemoryTree;
+
+    /// A tree with the given files and contents.
+    fn tree(root: &str, files: &[(&str, &str)]) -> MemoryTree {
+        files
+            .iter()
+            .fold(MemoryTree::new(root), |tree, (path, contents)| {
+                tree.file(path, *contents)
+            })
+    }
+
+    fn three_way(
+        base: &[(&str, &str)],
+        ours: &[(&str, &str)],
+        theirs: &[(&str, &str)],
+    ) -> Result<Vec<(PathBuf, ThreeWayTag)>> {
+        let diff = ThreeWayDiff::with_sources(
+            tree("base", base).into(),
+            tree("ours", ours).into(),
+            tree("theirs", theirs).into(),
+            DiffOpts::new(),
+        )?;
+        Ok(diff
+            .iter()
+            .filter(|entry| entry.tag() != ThreeWayTag::Unchanged)
+            .map(|entry| (entry.relative().to_owned(), entry.tag()))
+            .collect())
+    }
+
+    #[test]
+    fn test_three_way() -> Result<()> {
+        let base = tree(
+            "base",
+            &[
+                ("a/x", "x\n"),
+                ("a/y", "y\n"),
+                ("doggy", "doggy\n"),
+                ("fixed", "broken\n"),
+                ("gone", "gone\n"),
+                ("puppy", "puppy\n"),
+                ("same", "same\n"),
+            ],
+        );
+        let ours = tree(
+            "ours",
+            &[
+                ("a/x", "ours\n"),
+                ("a/y", "y\n"),
+                ("added", "ours\n"),
+                ("doggy", "kitty\n"),
+                ("fixed", "fixed\n"),
+                ("puppy", "ours\n"),
+                ("same", "same\n"),
+            ],
+        );
+        let theirs = tree(
+            "theirs",
+            &[
+                ("a/x", "x\n"),
+                ("a/y", "theirs\n"),
+                ("added", "theirs\n"),
+                ("doggy", "doggy\n"),
+                ("fixed", "fixed\n"),
+                ("new", "new\n"),
+                ("puppy", "theirs\n"),
+                ("same", "same\n"),
+            ],
+        );
+
+        let diff = ThreeWayDiff::with_sources(
+            base.into(),
+            ours.clone().into(),
+            theirs.into(),
+            DiffOpts::new(),
+        )?;
+        assert_eq!(
+            diff.to_string(),
+            indoc::indoc!(
+                "
+                ~  a/x
+                 ~ a/y
+                ++ added (conflict)
+                ~  doggy
+                ~~ fixed
+                -- gone
+                 + new
+                ~~ puppy (conflict)
+                "
+            )
+        );
+        let tags = diff
+            .iter()
+            .map(|entry| (entry.relative(), entry.tag()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            [
+                (Path::new("a"), ThreeWayTag::Unchanged),
+                (Path::new("a/x"), ThreeWayTag::Ours),
+                (Path::new("a/y"), ThreeWayTag::Theirs),
+                (Path::new("added"), ThreeWayTag::Conflict),
+                (Path::new("doggy"), ThreeWayTag::Ours),
+                (Path::new("fixed"), ThreeWayTag::Both),
+                (Path::new("gone"), ThreeWayTag::Both),
+                (Path::new("new"), ThreeWayTag::Theirs),
+                (Path::new("puppy"), ThreeWayTag::Conflict),
+                (Path::new("same"), ThreeWayTag::Unchanged),
+            ]
+        );
+        let new = diff.get(Path::new("new")).unwrap();
+        assert!(new.ours().is_none());
+        assert_eq!(new.theirs().unwrap().tag(), DiffTag::Insert);
+
+        // A path which changed type in one side and changed contents in the other conflicts.
+        let mut dir = TempTree::new().unwrap();
+        dir.dir("a")
+            .unwrap()
+            .file("a/x", "ours\n")
+            .unwrap()
+            .file("a/y", "y\n")
+            .unwrap()
+            .dir("doggy")
+            .unwrap();
+        let diff = ThreeWayDiff::with_sources(
+            tree(
+                "base",
+                &[("a/x", "x\n"), ("a/y", "y\n"), ("doggy", "doggy\n")],
+            )
+            .into(),
+            Source::dir(dir.as_ref()),
+            tree(
+                "theirs",
+                &[("a/x", "x\n"), ("a/y", "y\n"), ("doggy", "kitty\n")],
+            )
+            .into(),
+            DiffOpts::new(),
+        )?;
+        assert_eq!(diff.to_string(), "~  a/x\n!~ doggy/ (conflict)\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_three_way_delete_modify() -> Result<()> {
+        let base = [
+            ("d/e/f", "f\n"),
+            ("d/x", "x\n"),
+            ("d/y", "y\n"),
+            ("z", "z\n"),
+        ];
+        let deleted = [("z", "z\n")];
+
+        // Changes to the contents of a directory which the other side deleted conflict, and so
+        // does the directory.
+        let modified = [
+            ("d/e/f", "ours\n"),
+            ("d/x", "x\n"),
+            ("d/y", "y\n"),
+            ("z", "z\n"),
+        ];
+        assert_eq!(
+            three_way(&base, &modified, &deleted)?,
+            [
+                (PathBuf::from("d"), ThreeWayTag::Conflict),
+                (PathBuf::from("d/e"), ThreeWayTag::Conflict),
+                (PathBuf::from("d/e/f"), ThreeWayTag::Conflict),
+            ]
+        );
+        let diff = ThreeWayDiff::with_sources(
+            tree("base", &base).into(),
+            tree("ours", &modified).into(),
+            tree("theirs", &deleted).into(),
+            DiffOpts::new(),
+        )?;
+        assert_eq!(
+            diff.to_string(),
+            " - d/ (conflict)\n - d/e/ (conflict)\n~- d/e/f (conflict)\n"
+        );
+        let added = [
+            ("d/e/f", "f\n"),
+            ("d/new", "new\n"),
+            ("d/x", "x\n"),
+            ("d/y", "y\n"),
+        ];
+        assert_eq!(
+            three_way(&base, &deleted, &added)?,
+            [
+                (PathBuf::from("d"), ThreeWayTag::Conflict),
+                (PathBuf::from("d/new"), ThreeWayTag::Conflict),
+                (PathBuf::from("z"), ThreeWayTag::Theirs),
+            ]
+        );
+
+        // Paths which were deleted from both sides don't conflict.
+        let partly_deleted = [("d/e/f", "f\n"), ("z", "z\n")];
+        assert_eq!(
+            three_way(&base, &deleted, &partly_deleted)?,
+            [
+                (PathBuf::from("d"), ThreeWayTag::Ours),
+                (PathBuf::from("d/x"), ThreeWayTag::Both),
+                (PathBuf::from("d/y"), ThreeWayTag::Both),
+            ]
+        );
+        let diff = ThreeWayDiff::with_sources(
+            tree("base", &base).into(),
+            tree("ours", &deleted).into(),
+            tree("theirs", &partly_deleted).into(),
+            DiffOpts::new(),
+        )?;
+        // Paths inside a deleted directory are displayed as deleted too.
+        assert_eq!(diff.to_string(), "-  d/\n-- d/x\n-- d/y\n");
+        assert_eq!(
+            three_way(&base, &deleted, &deleted)?,
+            [(PathBuf::from("d"), ThreeWayTag::Both)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_three_way_add_add() -> Result<()> {
+        let base = [("z", "z\n")];
+        let ours = [("n/x", "x\n"), ("n/y/z", "z\n"), ("z", "z\n")];
+
+        assert_eq!(
+            three_way(&base, &ours, &ours)?,
+            [(PathBuf::from("n"), ThreeWayTag::Both)]
+        );
+
+        // Directories which were added with different contents conflict, even if they only
+        // differ deep inside.
+        for theirs in [
+            [("n/x", "x\n"), ("n/y/z", "theirs\n"), ("z", "z\n")],
+            [("n/x", "x\n"), ("n/y/zz", "z\n"), ("z", "z\n")],
+        ] {
+            assert_eq!(
+                three_way(&base, &ours, &theirs)?,
+                [(PathBuf::from("n"), ThreeWayTag::Conflict)]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_three_way_nested() -> Result<()> {
+        let base = [("a/b/x", "x\n"), ("a/b/y", "y\n"), ("a/c", "c\n")];
+
+        // Changes to different paths in the same directory don't conflict.
+        assert_eq!(
+            three_way(
+                &base,
+                &[("a/b/x", "ours\n"), ("a/b/y", "y\n"), ("a/c", "c\n")],
+                &[("a/b/x", "x\n"), ("a/b/y", "theirs\n"), ("a/c", "theirs\n")],
+            )?,
+            [
+                (PathBuf::from("a/b/x"), ThreeWayTag::Ours),
+                (PathBuf::from("a/b/y"), ThreeWayTag::Theirs),
+                (PathBuf::from("a/c"), ThreeWayTag::Theirs),
+            ]
+        );
+
+        assert_eq!(
+            three_way(
+                &base,
+                &[("a/b/x", "same\n"), ("a/b/y", "ours\n"), ("a/c", "c\n")],
+                &[("a/b/x", "same\n"), ("a/b/y", "theirs\n"), ("a/c", "c\n")],
+            )?,
+            [
+                (PathBuf::from("a/b/x"), ThreeWayTag::Both),
+                (PathBuf::from("a/b/y"), ThreeWayTag::Conflict),
+            ]
+        );
+
+        Ok(())
+    }
+}
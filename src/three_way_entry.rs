@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use iddqd::IdOrdItem;
+use iddqd::id_upcast;
+
+use crate::DiffEntry;
+use crate::DiffTag;
+use crate::DisplayDiffOpts;
+use crate::ThreeWayTag;
+
+/// A single entry in a [`crate::ThreeWayDiff`], identified by a path relative to the trees being
+/// diffed.
+#[derive(Debug, Clone)]
+pub struct ThreeWayEntry<'a> {
+    pub(crate) relative: PathBuf,
+    pub(crate) tag: ThreeWayTag,
+    pub(crate) ours: Option<DiffEntry<'a>>,
+    pub(crate) theirs: Option<DiffEntry<'a>>,
+}
+
+impl<'a> IdOrdItem for ThreeWayEntry<'a> {
+    type Key<'b>
+        = &'b Path
+    where
+        Self: 'b;
+
+    fn key(&self) -> Self::Key<'_> {
+        self.relative.as_path()
+    }
+
+    id_upcast! {}
+}
+
+impl<'a> ThreeWayEntry<'a> {
+    /// The path of this entry, relative to the trees being diffed.
+    pub fn relative(&self) -> &Path {
+        &self.relative
+    }
+
+    /// How this path changed in 'ours' and 'theirs'.
+    pub fn tag(&self) -> ThreeWayTag {
+        self.tag
+    }
+
+    /// The entry for this path in the diff from the base to 'ours'.
+    ///
+    /// This is `None` if the path is in neither the base nor 'ours', or if it's inside a
+    /// directory which was deleted from 'ours'.
+    pub fn ours(&self) -> Option<&DiffEntry<'a>> {
+        self.ours.as_ref()
+    }
+
+    /// The entry for this path in the diff from the base to 'theirs'.
+    ///
+    /// This is `None` if the path is in neither the base nor 'theirs', or if it's inside a
+    /// directory which was deleted from 'theirs'.
+    pub fn theirs(&self) -> Option<&DiffEntry<'a>> {
+        self.theirs.as_ref()
+    }
+
+    pub(crate) fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        opts: &DisplayDiffOpts,
+    ) -> std::fmt::Result {
+        if self.tag == ThreeWayTag::Unchanged {
+            return Ok(());
+        }
+
+        let marker = |entry: Option<&DiffEntry<'_>>, changed: bool| match entry {
+            Some(entry) if is_changed(entry) => entry.tag.marker(),
+            // A changed path without an entry is inside a deleted directory.
+            None if changed => DiffTag::Delete.marker(),
+            _ => ' ',
+        };
+        let path = self
+            .ours
+            .as_ref()
+            .or(self.theirs.as_ref())
+            .map(DiffEntry::format_path)
+            .unwrap_or_default();
+        let (ours_changed, theirs_changed) = match self.tag {
+            ThreeWayTag::Unchanged => (false, false),
+            ThreeWayTag::Ours => (true, false),
+            ThreeWayTag::Theirs => (false, true),
+            ThreeWayTag::Both | ThreeWayTag::Conflict => (true, true),
+        };
+        let mut line = format!(
+            "{}{} {path}",
+            marker(self.ours.as_ref(), ours_changed),
+            marker(self.theirs.as_ref(), theirs_changed)
+        );
+        if self.tag == ThreeWayTag::Conflict {
+            line.push_str(" (conflict)");
+        }
+
+        let style = if opts.color {
+            self.tag.style()
+        } else {
+            owo_colors::Style::new()
+        };
+        writeln!(f, "{}", style.style(line))
+    }
+}
+
+/// Whether a path itself changed.
+///
+/// Changed directories are implied by their changed contents, so a directory only counts as
+/// changed if its metadata changed (or it was added, removed, or changed type).
+pub(crate) fn is_changed(entry: &DiffEntry<'_>) -> bool {
+    match entry.tag {
+        DiffTag::Equal => false,
+        DiffTag::Replace if entry.is_dir() => entry.changes.metadata(),
+        _ => true,
+    }
+}
@@ -0,0 +1,30 @@
+use owo_colors::Style;
+
+/// How a path changed in a [`crate::ThreeWayDiff`], relative to the common base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreeWayTag {
+    /// An entry that is unchanged in both 'ours' and 'theirs'.
+    Unchanged,
+    /// An entry that changed in 'ours' and is unchanged in 'theirs'.
+    Ours,
+    /// An entry that changed in 'theirs' and is unchanged in 'ours'.
+    Theirs,
+    /// An entry that changed the same way in both 'ours' and 'theirs', like a file which was
+    /// deleted from both or changed to the same contents in both.
+    Both,
+    /// An entry that changed differently in 'ours' and 'theirs'.
+    Conflict,
+}
+
+impl ThreeWayTag {
+    /// The style used to display entries with this tag when colors are enabled.
+    pub(crate) fn style(&self) -> Style {
+        match self {
+            ThreeWayTag::Unchanged => Style::new(),
+            ThreeWayTag::Ours => Style::new().green(),
+            ThreeWayTag::Theirs => Style::new().blue(),
+            ThreeWayTag::Both => Style::new().cyan(),
+            ThreeWayTag::Conflict => Style::new().red().bold(),
+        }
+    }
+}
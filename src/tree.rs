@@ -0,0 +1,95 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::HashError;
+use crate::Result;
+use crate::stat::Stat;
+
+/// A directory tree which can be diffed, like a directory on the local filesystem, a
+/// [`crate::Manifest`], or an archive.
+///
+/// Implement this to diff your own trees (e.g. a virtual filesystem), and diff them with
+/// [`crate::Source::tree`]. Paths are always relative to the root of the tree, and the root itself
+/// is the empty path. Use [`crate::TreeError`] to report errors.
+pub trait Tree: Debug + Send + Sync {
+    /// The path of the tree, used in error messages and as [`crate::PathInfo::base`].
+    ///
+    /// This doesn't need to exist on the local filesystem.
+    fn root(&self) -> &Path;
+
+    /// List the children of the directory at `relative`, in any order.
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>>;
+
+    /// Get the metadata for the path at `relative`.
+    ///
+    /// The root (the empty path) must be a directory.
+    fn stat(&self, relative: &Path) -> Result<Stat>;
+
+    /// Read the target of the symbolic link at `relative`.
+    fn read_link(&self, relative: &Path) -> Result<PathBuf>;
+
+    /// Open the file at `relative` for reading.
+    ///
+    /// Returns `None` if the tree doesn't record file contents, in which case files can only be
+    /// compared by their hashes.
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>>;
+
+    /// Hash the contents of the file at `relative` with [`blake3`].
+    ///
+    /// By default, this returns the hash given by [`Stat::with_hash`] if there is one, and
+    /// otherwise hashes the contents from [`Tree::open`].
+    fn hash_file(&self, relative: &Path, stat: &Stat) -> Result<blake3::Hash> {
+        if let Some(hash) = stat.hash {
+            return Ok(hash);
+        }
+
+        let path = self.root().join(relative);
+        let Some(reader) = self.open(relative)? else {
+            return Err(HashError {
+                path,
+                inner: std::io::Error::other("File contents are not available"),
+            }
+            .into());
+        };
+        let mut hasher = blake3::Hasher::new();
+        hasher
+            .update_reader(reader)
+            .map_err(|inner| HashError { path, inner })?;
+        Ok(hasher.finalize())
+    }
+}
+
+/// A child of a directory in a [`Tree`].
+#[derive(Debug)]
+pub struct TreeEntry {
+    pub(crate) name: OsString,
+    pub(crate) stat: Result<Stat>,
+}
+
+impl TreeEntry {
+    /// Construct an entry for the child with the given name, and its metadata or the error
+    /// encountered while reading it.
+    ///
+    /// Errors are reported (or tolerated, with [`crate::DiffOpts::tolerate_errors`]) if the child
+    /// isn't filtered out of the diff.
+    pub fn new(name: impl Into<OsString>, stat: Result<Stat>) -> Self {
+        Self {
+            name: name.into(),
+            stat,
+        }
+    }
+
+    /// The name of the child.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// The child's metadata, or the error encountered while reading it.
+    pub fn stat(&self) -> &Result<Stat> {
+        &self.stat
+    }
+}
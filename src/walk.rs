@@ -0,0 +1,655 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::Changes;
+use crate::DiffEntry;
+use crate::DiffOpts;
+use crate::DiffTag;
+use crate::LoopError;
+use crate::PathInfo;
+use crate::Result;
+use crate::Source;
+use crate::base::Base;
+use crate::candidate_changes::candidate_changes;
+use crate::candidate_changes::metadata_changes;
+use crate::content_diff::content_diff;
+use crate::deferred::Deferred;
+use crate::dir_stack::DirStack;
+use crate::error_sink::ErrorSink;
+use crate::filter::Filter;
+use crate::stat::Stat;
+use crate::strip_prefix::strip_prefix;
+use crate::tree::Tree;
+
+/// Receives each [`DiffEntry`] as soon as it's determined.
+pub(crate) type Sink<'s, 'a> = dyn FnMut(DiffEntry<'a>) -> Result<()> + 's;
+
+/// One of the two trees being diffed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    Old,
+    New,
+}
+
+/// A child of a directory being walked.
+#[derive(Debug)]
+struct Child {
+    name: OsString,
+    stat: Stat,
+}
+
+/// The filtered contents of a directory, sorted by name.
+#[derive(Debug, Default)]
+struct Listing {
+    children: Vec<Child>,
+    /// Children which couldn't be read.
+    failed: HashSet<OsString>,
+}
+
+/// The outcome of diffing a path.
+#[derive(Debug, Clone, Copy, Default)]
+struct Walked {
+    /// Whether the path changed.
+    changed: bool,
+    /// Whether the path, or anything inside it, was reported.
+    reported: bool,
+}
+
+impl Walked {
+    const CHANGED: Self = Self {
+        changed: true,
+        reported: true,
+    };
+
+    fn add(&mut self, other: Self) {
+        self.changed |= other.changed;
+        self.reported |= other.reported;
+    }
+}
+
+/// State for walking the old and new trees together.
+///
+/// Both trees are walked one directory at a time. Each directory's contents are listed in both
+/// trees, and then paths which are present in both trees are compared.
+#[derive(Debug)]
+pub(crate) struct Walk<'a, 'o> {
+    old: Arc<dyn Tree>,
+    new: Arc<dyn Tree>,
+    old_base: Base<'a>,
+    new_base: Base<'a>,
+    pub(crate) opts: &'o DiffOpts,
+    filter: Arc<Filter>,
+    pub(crate) errors: ErrorSink,
+    /// Entries held back until files are compared, if [`DiffOpts::parallel_compare`] is enabled.
+    deferred: Option<Deferred<'a>>,
+}
+
+impl<'a, 'o> Walk<'a, 'o> {
+    pub(crate) fn new(old: &Source<'a>, new: &Source<'a>, opts: &'o DiffOpts) -> Result<Self> {
+        Ok(Self {
+            old: old.open(opts),
+            new: new.open(opts),
+            old_base: old.base(),
+            new_base: new.base(),
+            opts,
+            filter: Arc::new(Filter::new(opts)?),
+            errors: ErrorSink::new(opts.tolerate_errors),
+            deferred: opts.parallel_compare.then(Deferred::default),
+        })
+    }
+
+    fn tree(&self, side: Side) -> &Arc<dyn Tree> {
+        match side {
+            Side::Old => &self.old,
+            Side::New => &self.new,
+        }
+    }
+
+    fn base(&self, side: Side) -> &Base<'a> {
+        match side {
+            Side::Old => &self.old_base,
+            Side::New => &self.new_base,
+        }
+    }
+
+    /// Walk both trees, passing each entry to the `sink`.
+    ///
+    /// Entries are produced in order, except that directories are produced after their contents.
+    pub(crate) fn run(&mut self, sink: &mut Sink<'_, 'a>) -> Result<()> {
+        let old = self.root(Side::Old)?;
+        let new = self.root(Side::New)?;
+        if let (Some(old), Some(new)) = (old, new) {
+            self.walk_dir(Path::new(""), &old, &new, sink)?;
+        }
+
+        if let Some(deferred) = self.deferred.take() {
+            for entry in deferred.compare(self.opts, &mut self.errors)? {
+                sink(entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit(&mut self, entry: DiffEntry<'a>, sink: &mut Sink<'_, 'a>) -> Result<()> {
+        match &mut self.deferred {
+            Some(deferred) => {
+                deferred.push(entry);
+                Ok(())
+            }
+            None => sink(entry),
+        }
+    }
+
+    fn root(&mut self, side: Side) -> Result<Option<DirStack>> {
+        let root = Path::new("");
+        let stat = self.tree(side).stat(root);
+        Ok(self
+            .errors
+            .recover(stat)?
+            .map(|stat| self.push_dir(side, &DirStack::default(), root, &stat)))
+    }
+
+    fn push_dir(&self, side: Side, dirs: &DirStack, relative: &Path, stat: &Stat) -> DirStack {
+        dirs.push(
+            &**self.tree(side),
+            relative,
+            stat,
+            self.filter.ignore_filenames(),
+        )
+    }
+
+    /// Enter the directory at `relative`, checking for symbolic link loops.
+    fn enter(
+        &mut self,
+        side: Side,
+        relative: &Path,
+        stat: &Stat,
+        dirs: &DirStack,
+    ) -> Result<Option<DirStack>> {
+        if self.opts.follow_links && dirs.contains(stat) {
+            let path = self.tree(side).root().join(relative);
+            self.errors.recover::<()>(Err(LoopError { path }.into()))?;
+            return Ok(None);
+        }
+        Ok(Some(self.push_dir(side, dirs, relative, stat)))
+    }
+
+    /// List the contents of the directory at `relative`.
+    ///
+    /// Returns `None` if the directory can't be read.
+    fn read_dir(
+        &mut self,
+        side: Side,
+        relative: &Path,
+        dirs: &DirStack,
+    ) -> Result<Option<Listing>> {
+        let entries = self.tree(side).read_dir(relative);
+        let Some(mut entries) = self.errors.recover(entries)? else {
+            return Ok(None);
+        };
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        let mut listing = Listing::default();
+        for entry in entries {
+            let is_dir = entry.stat.as_ref().is_ok_and(Stat::is_dir);
+            let child = relative.join(&entry.name);
+            if !self.filter.is_match(&child, is_dir) || dirs.is_ignored(&child, is_dir) {
+                continue;
+            }
+
+            match self.errors.recover(entry.stat)? {
+                Some(stat) => listing.children.push(Child {
+                    name: entry.name,
+                    stat,
+                }),
+                None => {
+                    listing.failed.insert(entry.name);
+                }
+            }
+        }
+
+        Ok(Some(listing))
+    }
+
+    /// Diff the contents of the directory at `relative`, which is present in both trees.
+    ///
+    /// Returns whether any of the directory's contents changed or were reported.
+    fn walk_dir(
+        &mut self,
+        relative: &Path,
+        old_dirs: &DirStack,
+        new_dirs: &DirStack,
+        sink: &mut Sink<'_, 'a>,
+    ) -> Result<Walked> {
+        let old = self.read_dir(Side::Old, relative, old_dirs)?;
+        let new = self.read_dir(Side::New, relative, new_dirs)?;
+        let (Some(old), Some(new)) = (old, new) else {
+            // We can't tell what's in this directory, so we can't say it's unchanged.
+            return Ok(Walked::CHANGED);
+        };
+
+        let failed = !old.failed.is_empty() || !new.failed.is_empty();
+        let mut walked = Walked {
+            changed: failed,
+            reported: failed,
+        };
+
+        let mut pairs = Vec::new();
+        let mut old_children = old.children.into_iter().peekable();
+        let mut new_children = new.children.into_iter().peekable();
+        loop {
+            let (old_child, new_child) = match (old_children.peek(), new_children.peek()) {
+                (None, None) => break,
+                (Some(_), None) => (old_children.next(), None),
+                (None, Some(_)) => (None, new_children.next()),
+                (Some(old_child), Some(new_child)) => match old_child.name.cmp(&new_child.name) {
+                    std::cmp::Ordering::Less => (old_children.next(), None),
+                    std::cmp::Ordering::Greater => (None, new_children.next()),
+                    std::cmp::Ordering::Equal => (old_children.next(), new_children.next()),
+                },
+            };
+
+            let name = &old_child
+                .as_ref()
+                .or(new_child.as_ref())
+                .expect("At least one child is present")
+                .name;
+            if old.failed.contains(name) || new.failed.contains(name) {
+                // We can't tell if this path is present in the other tree.
+                continue;
+            }
+            pairs.push((old_child, new_child));
+        }
+
+        if !self.opts.parallel_walk {
+            for (old_child, new_child) in pairs {
+                walked.add(
+                    self.walk_child(relative, old_child, new_child, old_dirs, new_dirs, sink)?,
+                );
+            }
+            return Ok(walked);
+        }
+
+        // Walk each child on its own thread, and then produce the results in order.
+        let results = pairs
+            .into_par_iter()
+            .map(|(old_child, new_child)| {
+                let mut walk = self.fork();
+                let mut entries = Vec::new();
+                let child_walked = walk.walk_child(
+                    relative,
+                    old_child,
+                    new_child,
+                    old_dirs,
+                    new_dirs,
+                    &mut |entry| {
+                        entries.push(entry);
+                        Ok(())
+                    },
+                );
+                (child_walked, entries, walk)
+            })
+            .collect::<Vec<_>>();
+
+        for (child_walked, entries, walk) in results {
+            if let (Some(deferred), Some(child_deferred)) = (&mut self.deferred, walk.deferred) {
+                deferred.append(child_deferred, self.errors.errors.len());
+            }
+            self.errors.errors.extend(walk.errors.errors);
+            for entry in entries {
+                self.emit(entry, sink)?;
+            }
+            walked.add(child_walked?);
+        }
+
+        Ok(walked)
+    }
+
+    fn path_info(&self, side: Side, stat: Stat) -> PathInfo<'a> {
+        PathInfo::new(stat, self.base(side).clone(), self.tree(side).clone())
+    }
+
+    /// A [`Walk`] for a subtree, which can be walked on another thread.
+    fn fork(&self) -> Self {
+        Self {
+            old: self.old.clone(),
+            new: self.new.clone(),
+            old_base: self.old_base.clone(),
+            new_base: self.new_base.clone(),
+            opts: self.opts,
+            filter: self.filter.clone(),
+            errors: ErrorSink::new(self.opts.tolerate_errors),
+            deferred: self.opts.parallel_compare.then(Deferred::default),
+        }
+    }
+
+    /// Diff a child of the directory at `relative`.
+    fn walk_child(
+        &mut self,
+        relative: &Path,
+        old_child: Option<Child>,
+        new_child: Option<Child>,
+        old_dirs: &DirStack,
+        new_dirs: &DirStack,
+        sink: &mut Sink<'_, 'a>,
+    ) -> Result<Walked> {
+        match (old_child, new_child) {
+            (Some(old_child), Some(new_child)) => self.walk_both(
+                relative.join(&old_child.name),
+                old_child.stat,
+                new_child.stat,
+                old_dirs,
+                new_dirs,
+                sink,
+            ),
+            (Some(old_child), None) => {
+                let relative = relative.join(&old_child.name);
+                if !self.is_reported(Side::Old, &relative, &old_child.stat, old_dirs) {
+                    return Ok(Walked::default());
+                }
+                let entry = DiffEntry::new(
+                    relative,
+                    DiffTag::Delete,
+                    Some(self.path_info(Side::Old, old_child.stat)),
+                    None,
+                );
+                self.emit(entry, sink)?;
+                Ok(Walked::CHANGED)
+            }
+            (None, Some(new_child)) => {
+                let relative = relative.join(&new_child.name);
+                if !self.is_reported(Side::New, &relative, &new_child.stat, new_dirs) {
+                    return Ok(Walked::default());
+                }
+                let entry = DiffEntry::new(
+                    relative,
+                    DiffTag::Insert,
+                    None,
+                    Some(self.path_info(Side::New, new_child.stat)),
+                );
+                self.emit(entry, sink)?;
+                Ok(Walked::CHANGED)
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Diff the path at `relative`, which is present in both trees.
+    fn walk_both(
+        &mut self,
+        relative: PathBuf,
+        old_stat: Stat,
+        new_stat: Stat,
+        old_dirs: &DirStack,
+        new_dirs: &DirStack,
+        sink: &mut Sink<'_, 'a>,
+    ) -> Result<Walked> {
+        let deleted = self.path_info(Side::Old, old_stat);
+        let inserted = self.path_info(Side::New, new_stat);
+
+        let assumed_equal = self.opts.quick_check
+            && deleted.stat.is_file()
+            && inserted.stat.is_file()
+            && deleted.stat.size == inserted.stat.size
+            && deleted.stat.mtime.is_some()
+            && deleted.stat.mtime == inserted.stat.mtime;
+
+        // Files are compared later if we're comparing them in parallel.
+        let pending = self.deferred.is_some()
+            && !assumed_equal
+            && deleted.stat.file_type == inserted.stat.file_type
+            && !deleted.stat.is_dir()
+            && !deleted.stat.is_symlink();
+
+        let changes = if assumed_equal || pending {
+            Ok(metadata_changes(&deleted, &inserted, self.opts))
+        } else {
+            candidate_changes(&relative, &deleted, &inserted, self.opts)
+        };
+        let mut changes = self
+            .errors
+            .recover(changes)?
+            // If we can't compare the paths, we can't say they're the same.
+            .unwrap_or(Changes {
+                content: true,
+                ..Default::default()
+            });
+
+        if deleted.stat.file_type != inserted.stat.file_type {
+            let old_dir = deleted.stat.is_dir().then(|| deleted.stat.clone());
+            let new_dir = inserted.stat.is_dir().then(|| inserted.stat.clone());
+
+            // If a directory was replaced, its contents were deleted or inserted. Like other
+            // directories, it's produced after its contents.
+            if let Some(stat) = old_dir {
+                self.walk_one(Side::Old, &relative, &stat, old_dirs, sink)?;
+            }
+            if let Some(stat) = new_dir {
+                self.walk_one(Side::New, &relative, &stat, new_dirs, sink)?;
+            }
+
+            let mut entry =
+                DiffEntry::new(relative, DiffTag::TypeChange, Some(deleted), Some(inserted));
+            entry.changes = changes;
+            self.emit(entry, sink)?;
+
+            return Ok(Walked::CHANGED);
+        }
+
+        if deleted.stat.is_dir() {
+            let old_dirs = self.enter(Side::Old, &relative, &deleted.stat, old_dirs)?;
+            let new_dirs = self.enter(Side::New, &relative, &inserted.stat, new_dirs)?;
+            let walked = match (old_dirs, new_dirs) {
+                (Some(old_dirs), Some(new_dirs)) => {
+                    self.walk_dir(&relative, &old_dirs, &new_dirs, sink)?
+                }
+                _ => Walked::CHANGED,
+            };
+            if !walked.reported && !self.filter.is_included(&relative) {
+                // Nothing in this directory is included.
+                return Ok(Walked::default());
+            }
+            changes.content = walked.changed;
+        }
+
+        let tag = if changes.is_empty() {
+            DiffTag::Equal
+        } else {
+            DiffTag::Replace
+        };
+
+        let content_diff = if self.opts.text_diff
+            && !pending
+            && changes.content
+            && deleted.stat.is_file()
+            && inserted.stat.is_file()
+        {
+            self.errors
+                .recover(content_diff(
+                    &relative,
+                    &deleted,
+                    &inserted,
+                    self.opts.context_lines,
+                ))?
+                .flatten()
+        } else {
+            None
+        };
+
+        let mut entry = DiffEntry::new(relative, tag, Some(deleted), Some(inserted));
+        entry.changes = changes;
+        entry.content_diff = content_diff;
+        entry.assumed_equal = assumed_equal;
+        match &mut self.deferred {
+            Some(deferred) if pending => deferred.push_pending(entry, self.errors.errors.len()),
+            _ => self.emit(entry, sink)?,
+        }
+
+        Ok(Walked {
+            changed: tag != DiffTag::Equal,
+            reported: true,
+        })
+    }
+
+    /// Report the contents of the directory at `relative`, which is only present in one tree,
+    /// as deleted or inserted.
+    fn walk_one(
+        &mut self,
+        side: Side,
+        relative: &Path,
+        stat: &Stat,
+        dirs: &DirStack,
+        sink: &mut Sink<'_, 'a>,
+    ) -> Result<()> {
+        let Some(dirs) = self.enter(side, relative, stat, dirs)? else {
+            return Ok(());
+        };
+        let Some(listing) = self.read_dir(side, relative, &dirs)? else {
+            return Ok(());
+        };
+
+        for child in listing.children {
+            let relative = relative.join(&child.name);
+            if !self.is_reported(side, &relative, &child.stat, &dirs) {
+                continue;
+            }
+            let info = self.path_info(side, child.stat);
+            let entry = match side {
+                Side::Old => DiffEntry::new(relative, DiffTag::Delete, Some(info), None),
+                Side::New => DiffEntry::new(relative, DiffTag::Insert, None, Some(info)),
+            };
+            self.emit(entry, sink)?;
+        }
+
+        Ok(())
+    }
+
+    /// Should the path at `relative`, which is only present in one tree, be reported?
+    ///
+    /// Directories which aren't included by [`DiffOpts::include`] are only reported if they
+    /// contain paths which are.
+    fn is_reported(&self, side: Side, relative: &Path, stat: &Stat, dirs: &DirStack) -> bool {
+        !stat.is_dir()
+            || self.filter.is_included(relative)
+            || self.contains_included(side, relative, stat, dirs)
+    }
+
+    /// Does the directory at `relative` contain any paths which match [`DiffOpts::include`]?
+    ///
+    /// Errors aren't reported, because the directory's contents aren't. Directories which can't be
+    /// read are assumed to contain included paths.
+    fn contains_included(&self, side: Side, relative: &Path, stat: &Stat, dirs: &DirStack) -> bool {
+        if self.opts.follow_links && dirs.contains(stat) {
+            return false;
+        }
+        let dirs = self.push_dir(side, dirs, relative, stat);
+        let Ok(entries) = self.tree(side).read_dir(relative) else {
+            return true;
+        };
+
+        entries.into_iter().any(|entry| {
+            let is_dir = entry.stat.as_ref().is_ok_and(Stat::is_dir);
+            let child = relative.join(&entry.name);
+            if !self.filter.is_match(&child, is_dir) || dirs.is_ignored(&child, is_dir) {
+                return false;
+            }
+            match entry.stat {
+                Ok(stat) if is_dir => self.contains_included(side, &child, &stat, &dirs),
+                // Anything else which matches is included.
+                _ => true,
+            }
+        })
+    }
+
+    /// Hash the contents of the directory tree at `relative`, including the relative paths of
+    /// its entries.
+    ///
+    /// Returns `None` if the directory is empty or some of its contents can't be read.
+    pub(crate) fn hash_tree(
+        &mut self,
+        side: Side,
+        relative: &Path,
+    ) -> Result<Option<blake3::Hash>> {
+        tracing::trace!("Hashing tree {relative:?}");
+
+        // Load the ignore files from each parent directory.
+        let mut path = PathBuf::new();
+        let Some(mut dirs) = self.root(side)? else {
+            return Ok(None);
+        };
+        for component in relative.components() {
+            path.push(component);
+            let stat = self.tree(side).stat(&path);
+            let Some(stat) = self.errors.recover(stat)? else {
+                return Ok(None);
+            };
+            dirs = self.push_dir(side, &dirs, &path, &stat);
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        let mut empty = true;
+        let complete = self.hash_dir(side, relative, relative, &dirs, &mut hasher, &mut empty)?;
+        Ok((complete && !empty).then(|| hasher.finalize()))
+    }
+
+    /// Returns `false` if some of the directory's contents can't be read.
+    fn hash_dir(
+        &mut self,
+        side: Side,
+        root: &Path,
+        relative: &Path,
+        dirs: &DirStack,
+        hasher: &mut blake3::Hasher,
+        empty: &mut bool,
+    ) -> Result<bool> {
+        let Some(listing) = self.read_dir(side, relative, dirs)? else {
+            return Ok(false);
+        };
+        if !listing.failed.is_empty() {
+            return Ok(false);
+        }
+
+        for child in listing.children {
+            *empty = false;
+            let relative = relative.join(&child.name);
+
+            // Paths can't contain NUL bytes, so this is an unambiguous separator.
+            hasher.update(strip_prefix(&relative, root)?.as_os_str().as_bytes());
+            hasher.update(&[0]);
+
+            if child.stat.is_dir() {
+                hasher.update(b"d");
+                let Some(dirs) = self.enter(side, &relative, &child.stat, dirs)? else {
+                    return Ok(false);
+                };
+                if !self.hash_dir(side, root, &relative, &dirs, hasher, empty)? {
+                    return Ok(false);
+                }
+            } else if child.stat.is_symlink() {
+                hasher.update(b"l");
+                let target = self.tree(side).read_link(&relative);
+                let Some(target) = self.errors.recover(target)? else {
+                    return Ok(false);
+                };
+                hasher.update(target.as_os_str().as_bytes());
+                hasher.update(&[0]);
+            } else {
+                hasher.update(b"f");
+                let hash = self.tree(side).hash_file(&relative, &child.stat);
+                let Some(hash) = self.errors.recover(hash)? else {
+                    return Ok(false);
+                };
+                hasher.update(hash.as_bytes());
+            }
+        }
+
+        Ok(true)
+    }
+}
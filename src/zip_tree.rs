@@ -0,0 +1,304 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::Read;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use zip::ZipArchive;
+
+use crate::ArchiveError;
+use crate::FileType;
+use crate::HashError;
+use crate::ReadError;
+use crate::Result;
+use crate::archive_index::ArchiveEntry;
+use crate::archive_index::ArchiveIndex;
+use crate::archive_index::normalize;
+use crate::stat::Checksum;
+use crate::stat::Stat;
+use crate::tree::Tree;
+use crate::tree::TreeEntry;
+
+/// A zip archive, like a `.jar` or `.whl` file.
+///
+/// Members are listed from the archive's central directory when it's opened, and decompressed
+/// when they're read or hashed.
+#[derive(Debug)]
+pub(crate) struct ZipTree {
+    index: ArchiveIndex,
+    archive: Mutex<ZipArchive<BufReader<File>>>,
+}
+
+impl ZipTree {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let error = |inner: std::io::Error| ArchiveError {
+            path: path.to_owned(),
+            inner,
+        };
+
+        let file = File::open(path).map_err(error)?;
+        let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| error(err.into()))?;
+        let mut index = ArchiveIndex::new(path);
+
+        for i in 0..archive.len() {
+            let member = archive.by_index_raw(i).map_err(|err| error(err.into()))?;
+            let name = member.name().map_err(|err| error(err.into()))?;
+            let Some(relative) = normalize(Path::new(&*name)) else {
+                tracing::warn!("Skipping archive member with unsafe path {name:?}");
+                continue;
+            };
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+
+            let mut stat = Stat {
+                // Only present if the archive was created on a Unix system.
+                mode: member.unix_mode().map(|mode| mode & 0o7777),
+                ..Stat::new(FileType::File, member.size())
+            };
+            let is_dir = member.is_dir();
+            let is_symlink = member.is_symlink();
+            let crc32 = member.crc32();
+            drop(member);
+
+            let entry = if is_dir {
+                stat.file_type = FileType::Dir;
+                stat.size = 0;
+                ArchiveEntry::new(stat)
+            } else if is_symlink {
+                // Symbolic links store their targets as their contents.
+                stat.file_type = FileType::Symlink;
+                let target = read_member(&mut archive, i).map_err(error)?;
+                ArchiveEntry {
+                    target: Some(PathBuf::from(OsString::from_vec(target))),
+                    ..ArchiveEntry::new(stat)
+                }
+            } else {
+                stat.checksum = Some(Checksum::Crc32(crc32));
+                ArchiveEntry {
+                    index: Some(i),
+                    ..ArchiveEntry::new(stat)
+                }
+            };
+            index.insert(relative, entry);
+        }
+
+        Ok(Self {
+            index,
+            archive: Mutex::new(archive),
+        })
+    }
+
+    fn archive(&self) -> MutexGuard<'_, ZipArchive<BufReader<File>>> {
+        self.archive
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn read_member(
+    archive: &mut ZipArchive<BufReader<File>>,
+    index: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    archive.by_index(index)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+impl Tree for ZipTree {
+    fn root(&self) -> &Path {
+        self.index.path()
+    }
+
+    fn read_dir(&self, relative: &Path) -> Result<Vec<TreeEntry>> {
+        Ok(self.index.read_dir(relative))
+    }
+
+    fn stat(&self, relative: &Path) -> Result<Stat> {
+        self.index.stat(relative)
+    }
+
+    fn read_link(&self, relative: &Path) -> Result<PathBuf> {
+        self.index.read_link(relative)
+    }
+
+    fn open(&self, relative: &Path) -> Result<Option<Box<dyn Read + '_>>> {
+        let index = self.index.member(relative)?;
+        tracing::trace!("Reading {relative:?} from {:?}", self.index.path());
+        let contents = read_member(&mut self.archive(), index).map_err(|inner| ReadError {
+            path: self.index.path().join(relative),
+            inner,
+        })?;
+        Ok(Some(Box::new(Cursor::new(contents))))
+    }
+
+    fn hash_file(&self, relative: &Path, _stat: &Stat) -> Result<blake3::Hash> {
+        let index = self.index.member(relative)?;
+        tracing::trace!("Hashing {relative:?} from {:?}", self.index.path());
+        let mut archive = self.archive();
+        let mut hasher = blake3::Hasher::new();
+        archive
+            .by_index(index)
+            .map_err(std::io::Error::from)
+            .and_then(|member| hasher.update_reader(member))
+            .map_err(|inner| HashError {
+                path: self.index.path().join(relative),
+                inner,
+            })?;
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testlib::TempTree;
+
+    use super::*;
+    use crate::CompareMetadata;
+    use crate::Diff;
+    use crate::DiffOpts;
+    use crate::DiffTag;
+    use crate::Error;
+    use crate::Source;
+
+    #[test]
+    fn test_zip() -> Result<()> {
+        fn build(path: &Path, doggy: &str) {
+            use std::io::Write;
+            use zip::write::SimpleFileOptions;
+
+            let options = SimpleFileOptions::default();
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+            writer
+                .add_directory("a/", options.unix_permissions(0o755))
+                .unwrap();
+            writer
+                .start_file("a/puppy", options.unix_permissions(0o644))
+                .unwrap();
+            writer.write_all(b"puppy\n").unwrap();
+            writer
+                .start_file("doggy", options.unix_permissions(0o600))
+                .unwrap();
+            writer.write_all(doggy.as_bytes()).unwrap();
+            writer
+                .add_symlink("link", "a/puppy", options.unix_permissions(0o777))
+                .unwrap();
+            // `bin` is only implied by its contents.
+            writer
+                .start_file("bin/run", options.unix_permissions(0o755))
+                .unwrap();
+            writer.write_all(b"run\n").unwrap();
+            writer.finish().unwrap();
+        }
+
+        let mut tree = TempTree::new().unwrap();
+        tree.dir("a")
+            .unwrap()
+            .file("a/puppy", "puppy\n")
+            .unwrap()
+            .mode("a/puppy", 0o644)
+            .unwrap()
+            .file("doggy", "doggy\n")
+            .unwrap()
+            .mode("doggy", 0o644)
+            .unwrap()
+            .symlink("link", "a/puppy")
+            .unwrap()
+            .dir("bin")
+            .unwrap()
+            .file("bin/run", "run\n")
+            .unwrap()
+            .mode("bin/run", 0o755)
+            .unwrap();
+
+        let archives = TempTree::new().unwrap();
+        let archives = AsRef::<Path>::as_ref(&archives);
+        let old = archives.join("old.zip");
+        build(&old, "doggy\n");
+        let new = archives.join("new.jar");
+        build(&new, "kitty\n");
+
+        let opts = DiffOpts::new()
+            .follow_links(false)
+            .compare_metadata(CompareMetadata::new().mode(true));
+        let diff =
+            Diff::with_sources(Source::zip(&old)?, Source::dir(tree.as_ref()), opts.clone())?;
+        assert_eq!(diff.to_string(), "~ doggy (mode)\n");
+
+        let diff = Diff::with_sources(Source::zip(&old)?, Source::zip(&new)?, opts)?;
+        assert_eq!(diff.to_string(), "~ doggy\n");
+        // The checksums differ, so the files don't need to be hashed.
+        let doggy = diff.get(Path::new("doggy")).unwrap();
+        assert_eq!(doggy.deleted().unwrap().hash(), None);
+        let puppy = diff.get(Path::new("a/puppy")).unwrap();
+        assert_eq!(puppy.tag(), DiffTag::Equal);
+        assert_eq!(
+            puppy.deleted().unwrap().hash(),
+            Some(blake3::hash(b"puppy\n"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_corrupt() -> Result<()> {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "puppy",
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+            )
+            .unwrap();
+        writer.write_all(b"puppy contents\n").unwrap();
+        let valid = writer.finish().unwrap().into_inner();
+
+        let archives = TempTree::new().unwrap();
+        for (name, contents) in [
+            ("empty.zip", Vec::new()),
+            ("garbage.zip", b"puppy".repeat(100)),
+            ("truncated.zip", valid[..valid.len() - 10].to_vec()),
+        ] {
+            let path = archives.child(name);
+            std::fs::write(&path, contents).unwrap();
+            assert!(
+                matches!(Source::zip(&path), Err(Error::Archive(_))),
+                "{name}"
+            );
+        }
+        assert!(matches!(
+            Source::zip(&archives.child("missing.zip")),
+            Err(Error::Archive(_))
+        ));
+
+        // Corrupt contents aren't noticed until the member is read.
+        let corrupt = archives.child("corrupt.zip");
+        let position = valid
+            .windows(15)
+            .position(|window| window == b"puppy contents\n")
+            .unwrap();
+        let mut contents = valid.clone();
+        contents[position..position + 15].copy_from_slice(b"kitty contents\n");
+        std::fs::write(&corrupt, contents).unwrap();
+
+        let mut tree = TempTree::new().unwrap();
+        tree.file("puppy", "kitty contents\n").unwrap();
+        assert!(matches!(
+            Diff::with_sources(
+                Source::zip(&corrupt)?,
+                Source::dir(tree.as_ref()),
+                Default::default()
+            ),
+            Err(Error::Hash(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use std::io::Result;
 use std::ops::Deref;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
+use std::time::SystemTime;
 
 use fs_err as fs;
 use temp_dir::TempDir;
@@ -27,6 +29,28 @@ impl TempTree {
         fs::write(self.child(path), contents.as_ref().as_bytes())?;
         Ok(self)
     }
+
+    pub fn symlink(&mut self, path: impl AsRef<str>, target: impl AsRef<str>) -> Result<&mut Self> {
+        let path = path.as_ref();
+        fs::os::unix::fs::symlink(target.as_ref(), self.child(path))?;
+        Ok(self)
+    }
+
+    pub fn mode(&mut self, path: impl AsRef<str>, mode: u32) -> Result<&mut Self> {
+        let path = path.as_ref();
+        fs::set_permissions(self.child(path), std::fs::Permissions::from_mode(mode))?;
+        Ok(self)
+    }
+
+    pub fn mtime(&mut self, path: impl AsRef<str>, mtime: SystemTime) -> Result<&mut Self> {
+        let path = path.as_ref();
+        fs::File::options()
+            .write(true)
+            .open(self.child(path))?
+            .file()
+            .set_modified(mtime)?;
+        Ok(self)
+    }
 }
 
 impl AsRef<TempDir> for TempTree {